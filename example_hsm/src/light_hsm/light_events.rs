@@ -1,7 +1,9 @@
-use rust_hsm::events::StateEventConstraint;
+use rust_hsm::HsmEvent;
 use strum::Display;
 
-#[derive(Debug, Display)]
+// Not every event is exercised by the example driver in main.rs
+#[allow(dead_code)]
+#[derive(Debug, Display, HsmEvent)]
 pub enum LightEvents {
     Toggle,
     /// Sets the light to a value from 1-100
@@ -14,5 +16,3 @@ pub enum LightEvents {
     InvalidNumArgs(usize),
     Invalid,
 }
-
-impl StateEventConstraint for LightEvents {}
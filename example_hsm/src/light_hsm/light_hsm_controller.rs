@@ -1,4 +1,4 @@
-use rust_hsm::{errors::HSMResult, state_engine::HSM};
+use rust_hsm::{builder::HsmBuilder, errors::HSMResult, hsm, state_engine::HSM};
 
 use crate::light_hsm::{
     light_events::LightEvents,
@@ -21,25 +21,23 @@ impl LightControllerHsm {
     pub fn new(engine_log_level: log::LevelFilter) -> Self {
         let shared_data = LightHsmData::new(0);
 
-        let hsm = HSM::new("LightControllerHsm".to_string(), engine_log_level).unwrap();
-
-        let top_state = LightStateTop::new(shared_data.clone());
+        let mut builder = HsmBuilder::new("LightControllerHsm".to_string(), engine_log_level);
 
         // dimmer leverage's similar behavior to on in most cases!
         // the non-shared behavior they impl for themselves!
         // Hence on is dimmer's parent.
-        let state_on = LightStateOn::new(shared_data.clone(), hsm.get_delegate());
-        let state_dimmer = LightStateDimmer::new(shared_data.clone(), hsm.get_delegate());
-        let state_off = LightStateOff::new(shared_data.clone(), hsm.get_delegate());
-
-        hsm.add_state(top_state, LightStates::Top, None).unwrap();
-        hsm.add_state(state_on, LightStates::ON, Some(LightStates::Top))
-            .unwrap();
-        hsm.add_state(state_off, LightStates::OFF, Some(LightStates::Top))
-            .unwrap();
-        hsm.add_state(state_dimmer, LightStates::DIMMER, Some(LightStates::ON))
-            .unwrap();
-        hsm.init(LightStates::DIMMER as u16).unwrap();
+        hsm!(builder, LightStates {
+            Top: LightStateTop::new(shared_data.clone()) => {
+                ON(initial): LightStateOn::new(shared_data.clone(), builder.get_typed_delegate()) => {
+                    DIMMER: LightStateDimmer::new(shared_data.clone(), builder.get_delegate()),
+                },
+                OFF: LightStateOff::new(shared_data.clone(), builder.get_typed_delegate()),
+            }
+        });
+        builder
+            .add_transition_event(LightStates::ON, "TurnOff", LightStates::OFF)
+            .add_transition_event(LightStates::OFF, "TurnOn", LightStates::ON);
+        let hsm = builder.build(LightStates::DIMMER).unwrap();
 
         LightControllerHsm {
             hsm,
@@ -58,6 +56,11 @@ impl LightControllerHsm {
         self.hsm.dispatch_event(event)
     }
 
+    /// Graphviz diagram of the light controller, with the current state highlighted
+    pub fn to_dot(&self) -> String {
+        self.hsm.to_dot(true).unwrap()
+    }
+
     /// In a real HSM this is a BAD idea. DO NOT LEAK the data
     /// Only doing it here as an example and so some asserts can be done
     pub fn get_light_data(&self) -> LightHsmDataRef {
@@ -1,21 +1,29 @@
 use rust_hsm::{
+    errors::HSMError,
     state::StateIF,
-    state_engine_delegate::WeakDelegate,
+    typed_transitions::{TransitionTo, TypedDelegate, TypedState},
 };
 
 use crate::{
-    light_events::LightEvents, light_hsm_data::LightHsmDataRef, light_states::LightStates,
+    light_events::LightEvents, light_hsm::light_state_on::LightStateOn,
+    light_hsm_data::LightHsmDataRef, light_states::LightStates,
 };
 
 pub(crate) struct LightStateOff {
-    delegate: WeakDelegate<LightStates, LightEvents>,
+    delegate: TypedDelegate<Self, LightStates, LightEvents>,
     shared_data: LightHsmDataRef,
 }
 
+impl TypedState<LightStates> for LightStateOff {
+    const STATE: LightStates = LightStates::OFF;
+}
+
+impl TransitionTo<LightStateOn> for LightStateOff {}
+
 impl LightStateOff {
     pub fn new(
         shared_data: LightHsmDataRef,
-        delegate: WeakDelegate<LightStates, LightEvents>,
+        delegate: TypedDelegate<Self, LightStates, LightEvents>,
     ) -> Box<Self> {
         Box::new(Self {
             delegate,
@@ -28,15 +36,11 @@ impl LightStateOff {
     }
 
     fn handle_turn_on(&self) -> bool {
-        match self.delegate.upgrade() {
-            None => {
-                // log?
-                true
-            }
-            Some(temp_rc) => match temp_rc.change_state(LightStates::ON as u16) {
-                Ok(()) => true,
-                Err(_) => false,
-            },
+        match self.delegate.transition::<LightStateOn>() {
+            Ok(()) => true,
+            // log?
+            Err(HSMError::DelegateUpgradeFail(_)) => true,
+            Err(_) => false,
         }
     }
 }
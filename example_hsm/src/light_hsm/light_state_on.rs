@@ -1,18 +1,28 @@
-use rust_hsm::{state::StateIF, state_engine_delegate::WeakDelegate};
+use rust_hsm::{
+    state::StateIF,
+    typed_transitions::{TransitionTo, TypedDelegate, TypedState},
+};
 
 use crate::{
-    light_events::LightEvents, light_hsm_data::LightHsmDataRef, light_states::LightStates,
+    light_events::LightEvents, light_hsm::light_state_off::LightStateOff,
+    light_hsm_data::LightHsmDataRef, light_states::LightStates,
 };
 
 pub(crate) struct LightStateOn {
-    delegate: WeakDelegate<LightStates, LightEvents>,
+    delegate: TypedDelegate<Self, LightStates, LightEvents>,
     shared_data: LightHsmDataRef,
 }
 
+impl TypedState<LightStates> for LightStateOn {
+    const STATE: LightStates = LightStates::ON;
+}
+
+impl TransitionTo<LightStateOff> for LightStateOn {}
+
 impl LightStateOn {
     pub fn new(
         shared_data: LightHsmDataRef,
-        delegate: WeakDelegate<LightStates, LightEvents>,
+        delegate: TypedDelegate<Self, LightStates, LightEvents>,
     ) -> Box<Self> {
         Box::new(Self {
             delegate,
@@ -25,13 +35,7 @@ impl LightStateOn {
     }
 
     fn handle_turn_off(&self) -> bool {
-        match self.delegate.upgrade() {
-            None => false,
-            Some(rc) => match rc.change_state(LightStates::OFF as u16) {
-                Ok(()) => true,
-                Err(_) => false,
-            },
-        }
+        self.delegate.transition::<LightStateOff>().is_ok()
     }
 }
 
@@ -4,7 +4,35 @@ use light_hsm::{
     light_hsm_data, light_states, light_states::LightStates,
 };
 
+/// The hsm logs through the `log` facade.
+/// A real application would install env_logger (or similar), but a bare-bones logger
+/// is plenty for the example.
+struct ExampleLogger;
+
+impl log::Log for ExampleLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        println!(
+            "[{}][{}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: ExampleLogger = ExampleLogger;
+
 fn main() {
+    log::set_logger(&LOGGER)
+        .map(|()| log::set_max_level(log::LevelFilter::Info))
+        .expect("Failed to install the example logger");
+
     let light_hsm = LightControllerHsm::new(log::LevelFilter::Info);
 
     let starting_state = light_hsm.get_current_state();
@@ -117,4 +145,7 @@ fn main() {
         );
         data.borrow_mut().clear_counts();
     }
+
+    // Draw the controller, i.e. `cargo run | sed -n '/^digraph/,$p' | dot -Tsvg`
+    println!("{}", light_hsm.to_dot());
 }
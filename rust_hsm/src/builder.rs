@@ -0,0 +1,593 @@
+//! The only way to assemble an HSM.
+//! States are collected here and the whole topology is validated at once in build().
+//! The resulting HSM's topology can no longer change.
+#[cfg(feature = "serde")]
+use crate::config::{HsmConfig, StateRegistry};
+use crate::{
+    clock::SharedClock,
+    errors::HSMResult,
+    events::StateEventConstraint,
+    logger::HSMLogger,
+    machine_definition::MachineDefinition,
+    observer::SharedObserver,
+    state::{HistoryMode, StateBox, StateConstraint, StateHierarchy},
+    state_engine::{HSMEngine, SharedEngine, HSM},
+    state_engine_delegate::{SharedDelegate, WeakDelegate},
+    state_mapping::StateMapping,
+    transition_registry::TransitionRegistry,
+    typed_transitions::TypedDelegate,
+};
+use log::LevelFilter;
+
+use std::rc::Rc;
+
+pub struct HsmBuilder<StateT: StateConstraint, EventT: StateEventConstraint> {
+    engine: SharedEngine<StateT, EventT>,
+    logger: HSMLogger,
+    states: Vec<(StateT, Option<StateT>, StateBox<StateT, EventT>)>,
+    initial_children: Vec<(StateT, StateT)>,
+    history: Vec<(StateT, HistoryMode)>,
+    transitions: TransitionRegistry<StateT>,
+    /// Depths declared by add_declared_state, checked when building
+    declared_depths: Vec<(StateT, usize)>,
+}
+
+impl<StateT: StateConstraint + 'static, EventT: StateEventConstraint + 'static>
+    HsmBuilder<StateT, EventT>
+{
+    /// # Brief
+    /// Start building an HSM.
+    /// # Arguments
+    /// * `hsm_name` - The name of the HSM. Used for logging
+    /// * `logger_level` - The level of logging the HSM should use
+    pub fn new(hsm_name: String, logger_level: LevelFilter) -> Self {
+        Self::new_with_logger(hsm_name, HSMLogger::new(logger_level))
+    }
+
+    /// # Brief
+    /// Same as new, but with a fully configured logger (i.e. trace format and sinks).
+    pub fn new_with_logger(hsm_name: String, logger: HSMLogger) -> Self {
+        let mapping_logger = logger.clone().with_hsm_name(hsm_name.as_str());
+        Self {
+            engine: HSMEngine::new(hsm_name, logger),
+            logger: mapping_logger,
+            states: vec![],
+            initial_children: vec![],
+            history: vec![],
+            transitions: Default::default(),
+            declared_depths: vec![],
+        }
+    }
+
+    /// # Brief
+    /// The delegate handed to states so they can change state. Usable once built.
+    pub fn get_delegate(&self) -> WeakDelegate<StateT, EventT> {
+        let rc_dyn: SharedDelegate<StateT, EventT> = self.engine.clone();
+        Rc::downgrade(&rc_dyn)
+    }
+
+    /// # Brief
+    /// Same as get_delegate, but SourceT can only change state along its declared transitions.
+    pub fn get_typed_delegate<SourceT>(&self) -> TypedDelegate<SourceT, StateT, EventT> {
+        TypedDelegate::new(self.get_delegate())
+    }
+
+    /// # Brief
+    /// Add a state to be used by the HSM.
+    /// Nothing is checked until build().
+    /// # Arguments
+    /// * `state` - The state to add
+    /// * `state_id` - The id of the state
+    /// * `parent_state` - The parent of the state. None only for the top state.
+    pub fn add_state(
+        &mut self,
+        state: StateBox<StateT, EventT>,
+        state_id: StateT,
+        parent_state: Option<StateT>,
+    ) -> &mut Self {
+        self.states.push((state_id, parent_state, state));
+        self
+    }
+
+    /// # Brief
+    /// Same as add_state, but the state may only change to one of allowed_targets.
+    /// Any other change of state requested while it handles an event fails with IllegalTransition.
+    pub fn add_state_with_transitions(
+        &mut self,
+        state: StateBox<StateT, EventT>,
+        state_id: StateT,
+        parent_state: Option<StateT>,
+        allowed_targets: Vec<StateT>,
+    ) -> &mut Self {
+        self.transitions.declare(state_id, allowed_targets);
+        self.add_state(state, state_id, parent_state)
+    }
+
+    /// # Brief
+    /// Name the event on which source changes to target (i.e. for HSM::to_dot).
+    /// Only labels the edge: source is still only restricted by add_state_with_transitions.
+    pub fn add_transition_event(
+        &mut self,
+        source: StateT,
+        event_name: &str,
+        target: StateT,
+    ) -> &mut Self {
+        self.transitions.label_event(source, event_name, target);
+        self
+    }
+
+    /// # Brief
+    /// Make child the state actually entered whenever parent is the target of a change
+    /// (or the initial state). Applies recursively, if child has an initial child too.
+    pub fn set_initial_child(&mut self, parent_state: StateT, child_state: StateT) -> &mut Self {
+        self.initial_children.push((parent_state, child_state));
+        self
+    }
+
+    /// # Brief
+    /// Give a composite state history: whenever it is the target of a change,
+    /// what was active when it was last exited is resumed (in place of its initial child).
+    /// Has no effect until the state has been exited once.
+    /// Every change to the state resumes it, not just the ones through history (see HistoryMode).
+    pub fn set_history(&mut self, state: StateT, history_mode: HistoryMode) -> &mut Self {
+        self.history.push((state, history_mode));
+        self
+    }
+
+    /// # Brief
+    /// Add every state, initial child, history and transition of the definition.
+    /// States with transitions may only change to their targets (see add_state_with_transitions).
+    /// Build with `definition.initial_state` to start where the definition says.
+    /// # Arguments
+    /// * `definition` - The topology (i.e. loaded from SCXML)
+    /// * `create_state` - Creates the implementation of each state of the definition
+    pub fn add_machine_definition<F>(
+        &mut self,
+        definition: &MachineDefinition<StateT>,
+        mut create_state: F,
+    ) -> &mut Self
+    where
+        F: FnMut(StateT) -> StateBox<StateT, EventT>,
+    {
+        for (state_id, parent_state) in &definition.states {
+            self.add_state(create_state(*state_id), *state_id, *parent_state);
+        }
+        for (parent_state, child_state) in &definition.initial_children {
+            self.set_initial_child(*parent_state, *child_state);
+        }
+        for (state_id, history_mode) in &definition.history {
+            self.set_history(*state_id, *history_mode);
+        }
+        let mut allowed_targets: Vec<(StateT, Vec<StateT>)> = vec![];
+        for (source, event_name, target) in &definition.transitions {
+            match allowed_targets
+                .iter_mut()
+                .find(|(state, _)| state == source)
+            {
+                Some((_, targets)) if targets.contains(target) => {}
+                Some((_, targets)) => targets.push(*target),
+                None => allowed_targets.push((*source, vec![*target])),
+            }
+            self.add_transition_event(*source, event_name, *target);
+        }
+        for (source, targets) in allowed_targets {
+            self.transitions.declare(source, targets);
+        }
+        self
+    }
+
+    /// # Brief
+    /// Register an observer to be called back as the engine handles events.
+    /// Observers are called in the order they were added.
+    pub fn add_observer(&mut self, observer: SharedObserver<StateT, EventT>) -> &mut Self {
+        self.engine.add_observer(observer);
+        self
+    }
+
+    /// # Brief
+    /// Replace the time source used for residency and durations. Defaults to the system clock.
+    pub fn set_clock(&mut self, clock: SharedClock) -> &mut Self {
+        self.engine.set_clock(clock);
+        self
+    }
+
+    /// # Brief
+    /// Validate the topology and enter the initial state (or its initial child).
+    /// # Errors
+    /// * InvalidTopology listing every issue with the states and their parents
+    /// * The initial state was never added
+    pub fn build(self, initial_state: StateT) -> HSMResult<HSM<StateT, EventT>, StateT> {
+        let state_mapping = StateMapping::from_states(
+            self.states,
+            self.initial_children,
+            self.history,
+            self.transitions,
+            &self.declared_depths,
+            self.logger,
+        )?;
+        self.engine.init(state_mapping, initial_state)?;
+        Ok(HSM::new(self.engine))
+    }
+
+    /// # Brief
+    /// Add the hierarchy described by the config (creating every state through the registry)
+    /// and build with its initial state. Validated the same way as states added by hand.
+    /// # Arguments
+    /// * `config` - The hierarchy
+    /// * `registry` - Every state the config may refer to
+    /// * `event_names` - Every event name transitions may refer to
+    /// # Errors
+    /// * UnregisteredStates or UnknownEvents (see HsmConfig::to_machine_definition)
+    /// * Anything build() fails with
+    #[cfg(feature = "serde")]
+    pub fn build_from_config(
+        mut self,
+        config: &HsmConfig,
+        registry: &StateRegistry<StateT, EventT>,
+        event_names: &[&str],
+    ) -> HSMResult<HSM<StateT, EventT>, StateT> {
+        let definition = config.to_machine_definition(registry, event_names)?;
+        let delegate = self.get_delegate();
+        self.add_machine_definition(&definition, |state| {
+            registry
+                .create_state(state, delegate.clone())
+                .expect("Only registered states make it into the definition")
+        });
+        self.build(definition.initial_state)
+    }
+}
+
+impl<StateT: StateHierarchy, EventT: StateEventConstraint + 'static> HsmBuilder<StateT, EventT> {
+    /// # Brief
+    /// Add a state with the parent (and initial child) declared on its enum (see `#[hsm_states]`).
+    /// Its declared depth is checked when building, i.e. against a top state added by hand.
+    pub fn add_declared_state(
+        &mut self,
+        state: StateBox<StateT, EventT>,
+        state_id: StateT,
+    ) -> &mut Self {
+        self.declared_depths
+            .push((state_id, state_id.declared_depth()));
+        self.add_state(state, state_id, state_id.declared_parent());
+        if let Some(initial_child) = state_id.declared_initial_child() {
+            self.set_initial_child(state_id, initial_child);
+        }
+        self
+    }
+}
+
+/// # Brief
+/// Declare the whole tree of states at once, adding them to the builder.
+/// Every state is written as `Variant: constructor`, followed by `=> { children }` if it has any.
+/// Mark a child with `(initial)` to enter it in place of its parent (see set_initial_child).
+/// # Compile Errors
+/// * A variant of the state enum is missing from the tree
+/// * A variant is in the tree more than once (needs a fieldless enum, i.e. HsmState)
+/// # Example
+/// ```
+/// use rust_hsm::{builder::HsmBuilder, examples::*, hsm};
+///
+/// let mut builder = HsmBuilder::new("Example".to_string(), log::LevelFilter::Info);
+/// hsm!(builder, ExampleStates {
+///     Top: Top::new(builder.get_delegate()) => {
+///         LevelA1(initial): A1Impl::new(builder.get_delegate()) => {
+///             LevelA2(initial): A2Impl::new(builder.get_delegate()),
+///         },
+///         LevelB1: B1Impl::new(builder.get_delegate()),
+///     }
+/// });
+/// let hsm = builder.build(ExampleStates::Top).unwrap();
+/// assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA2);
+/// ```
+/// LevelB1 missing:
+/// ```compile_fail
+/// # use rust_hsm::{builder::HsmBuilder, examples::*, hsm};
+/// # let mut builder = HsmBuilder::new("Example".to_string(), log::LevelFilter::Info);
+/// hsm!(builder, ExampleStates {
+///     Top: Top::new(builder.get_delegate()) => {
+///         LevelA1: A1Impl::new(builder.get_delegate()) => {
+///             LevelA2: A2Impl::new(builder.get_delegate()),
+///         },
+///     }
+/// });
+/// ```
+/// LevelA2 twice:
+/// ```compile_fail
+/// # use rust_hsm::{builder::HsmBuilder, examples::*, hsm};
+/// # let mut builder = HsmBuilder::new("Example".to_string(), log::LevelFilter::Info);
+/// hsm!(builder, ExampleStates {
+///     Top: Top::new(builder.get_delegate()) => {
+///         LevelA1: A1Impl::new(builder.get_delegate()) => {
+///             LevelA2: A2Impl::new(builder.get_delegate()),
+///         },
+///         LevelB1: B1Impl::new(builder.get_delegate()),
+///         LevelA2: A2Impl::new(builder.get_delegate()),
+///     }
+/// });
+/// ```
+#[macro_export]
+macro_rules! hsm {
+    ($builder:ident, $states:ident { $top:ident : $($tree:tt)* }) => {{
+        $crate::hsm!(@check $states [] $top : $($tree)*);
+        $crate::hsm!(@top $builder, $states, $top : $($tree)*);
+    }};
+
+    (@top $builder:ident, $states:ident, $top:ident : $constructor:expr => { $($children:tt)* } $(,)?) => {
+        $crate::hsm!(@top $builder, $states, $top : $constructor);
+        $crate::hsm!(@children $builder, $states, $top; $($children)*);
+    };
+    (@top $builder:ident, $states:ident, $top:ident : $constructor:expr $(,)?) => {
+        let top_state = $constructor;
+        $builder.add_state(top_state, $states::$top, None);
+    };
+
+    (@children $builder:ident, $states:ident, $parent:ident;) => {};
+    (@children $builder:ident, $states:ident, $parent:ident; , $($rest:tt)*) => {
+        $crate::hsm!(@children $builder, $states, $parent; $($rest)*);
+    };
+    (@children $builder:ident, $states:ident, $parent:ident;
+        $state:ident $(($initial:ident))? : $constructor:expr => { $($children:tt)* } $($rest:tt)*) => {
+        $crate::hsm!(@children $builder, $states, $parent; $state $(($initial))? : $constructor);
+        $crate::hsm!(@children $builder, $states, $state; $($children)*);
+        $crate::hsm!(@children $builder, $states, $parent; $($rest)*);
+    };
+    (@children $builder:ident, $states:ident, $parent:ident;
+        $state:ident $(($initial:ident))? : $constructor:expr $(, $($rest:tt)*)?) => {
+        let state = $constructor;
+        $builder.add_state(state, $states::$state, Some($states::$parent));
+        $($crate::hsm!(@initial $initial $builder, $states, $parent, $state);)?
+        $crate::hsm!(@children $builder, $states, $parent; $($($rest)*)?);
+    };
+
+    (@initial initial $builder:ident, $states:ident, $parent:ident, $state:ident) => {
+        $builder.set_initial_child($states::$parent, $states::$state);
+    };
+
+    // Every variant must be in the tree (exhaustive match) exactly once (const assert)
+    (@check $states:ident [$($names:ident)*]) => {
+        let _ = |state: $states| match state {
+            $($states::$names => (),)*
+        };
+        const _: () = {
+            let states = [$($states::$names as isize),*];
+            let mut i = 0;
+            while i < states.len() {
+                let mut j = i + 1;
+                while j < states.len() {
+                    assert!(states[i] != states[j], "A state is in the hsm! tree more than once");
+                    j += 1;
+                }
+                i += 1;
+            }
+        };
+    };
+    (@check $states:ident [$($names:ident)*] , $($rest:tt)*) => {
+        $crate::hsm!(@check $states [$($names)*] $($rest)*);
+    };
+    (@check $states:ident [$($names:ident)*]
+        $state:ident $(($initial:ident))? : $constructor:expr => { $($children:tt)* } $($rest:tt)*) => {
+        $crate::hsm!(@check $states [$($names)* $state] $($children)* $($rest)*);
+    };
+    (@check $states:ident [$($names:ident)*]
+        $state:ident $(($initial:ident))? : $constructor:expr $(, $($rest:tt)*)?) => {
+        $crate::hsm!(@check $states [$($names)* $state] $($($rest)*)?);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        errors::HSMError,
+        examples::{A1Impl, A2Impl, B1Impl, ExampleEvents, ExampleStates, Top},
+        test_utils::create_test_builder,
+        topology_validation::TopologyIssue,
+    };
+
+    type ExampleBuilder = HsmBuilder<ExampleStates, ExampleEvents>;
+
+    fn new_builder() -> ExampleBuilder {
+        HsmBuilder::new("BuilderTest".to_string(), LevelFilter::Info)
+    }
+
+    #[test]
+    fn builds_valid_topology() {
+        let mut builder = new_builder();
+        let delegate = builder.get_delegate();
+        builder
+            .add_state(Top::new(delegate.clone()), ExampleStates::Top, None)
+            .add_state(
+                A1Impl::new(delegate.clone()),
+                ExampleStates::LevelA1,
+                Some(ExampleStates::Top),
+            )
+            .add_state(
+                B1Impl::new(delegate),
+                ExampleStates::LevelB1,
+                Some(ExampleStates::Top),
+            );
+        let hsm = builder.build(ExampleStates::LevelB1).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelB1);
+    }
+
+    #[test]
+    fn rejects_invalid_topologies() {
+        // Two tops
+        let mut builder = new_builder();
+        let delegate = builder.get_delegate();
+        builder
+            .add_state(Top::new(delegate.clone()), ExampleStates::Top, None)
+            .add_state(A1Impl::new(delegate), ExampleStates::LevelA1, None);
+        assert!(matches!(
+            builder.build(ExampleStates::Top),
+            Err(HSMError::InvalidTopology(issues))
+                if issues == vec![TopologyIssue::MultipleRoots(vec![
+                    ExampleStates::Top,
+                    ExampleStates::LevelA1
+                ])]
+        ));
+
+        // Duplicate
+        let mut builder = new_builder();
+        let delegate = builder.get_delegate();
+        builder
+            .add_state(Top::new(delegate.clone()), ExampleStates::Top, None)
+            .add_state(
+                A1Impl::new(delegate.clone()),
+                ExampleStates::LevelA1,
+                Some(ExampleStates::Top),
+            )
+            .add_state(
+                A1Impl::new(delegate),
+                ExampleStates::LevelA1,
+                Some(ExampleStates::Top),
+            );
+        assert!(matches!(
+            builder.build(ExampleStates::Top),
+            Err(HSMError::InvalidTopology(issues))
+                if issues == vec![TopologyIssue::DuplicateState(ExampleStates::LevelA1)]
+        ));
+
+        // Parent never added
+        let mut builder = new_builder();
+        let delegate = builder.get_delegate();
+        builder
+            .add_state(Top::new(delegate.clone()), ExampleStates::Top, None)
+            .add_state(
+                A2Impl::new(delegate),
+                ExampleStates::LevelA2,
+                Some(ExampleStates::LevelA1),
+            );
+        assert!(matches!(
+            builder.build(ExampleStates::Top),
+            Err(HSMError::InvalidTopology(_))
+        ));
+
+        // No top, A1 and A2 are each other's parent
+        let mut builder = new_builder();
+        let delegate = builder.get_delegate();
+        builder
+            .add_state(
+                A1Impl::new(delegate.clone()),
+                ExampleStates::LevelA1,
+                Some(ExampleStates::LevelA2),
+            )
+            .add_state(
+                A2Impl::new(delegate),
+                ExampleStates::LevelA2,
+                Some(ExampleStates::LevelA1),
+            );
+        assert!(matches!(
+            builder.build(ExampleStates::LevelA1),
+            Err(HSMError::InvalidTopology(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let mut builder = new_builder();
+        let delegate = builder.get_delegate();
+        builder
+            .add_state(Top::new(delegate.clone()), ExampleStates::Top, None)
+            .add_state(
+                A1Impl::new(delegate.clone()),
+                ExampleStates::LevelA1,
+                Some(ExampleStates::LevelA2),
+            )
+            .add_state(
+                A2Impl::new(delegate),
+                ExampleStates::LevelA2,
+                Some(ExampleStates::LevelA1),
+            );
+        assert!(matches!(
+            builder.build(ExampleStates::Top),
+            Err(HSMError::InvalidTopology(_))
+        ));
+    }
+
+    #[test]
+    fn initial_children() {
+        let mut builder = create_test_builder();
+        builder
+            .set_initial_child(ExampleStates::Top, ExampleStates::LevelA1)
+            .set_initial_child(ExampleStates::LevelA1, ExampleStates::LevelA2);
+        let hsm = builder.build(ExampleStates::Top).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA2);
+
+        // Leaving A1 for B1 (and coming back) lands in A2 again
+        hsm.dispatch_event(ExampleEvents::F(Default::default()))
+            .unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelB1);
+        let delegate = hsm.get_delegate().upgrade().unwrap();
+        delegate.change_state(ExampleStates::LevelA1).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA2);
+
+        let mut builder = create_test_builder();
+        builder.set_initial_child(ExampleStates::Top, ExampleStates::LevelA2);
+        assert!(matches!(
+            builder.build(ExampleStates::Top),
+            Err(HSMError::InvalidTopology(issues))
+                if issues == vec![TopologyIssue::InvalidInitialChild {
+                    parent: ExampleStates::Top,
+                    child: ExampleStates::LevelA2
+                }]
+        ));
+    }
+
+    #[test]
+    fn history() {
+        let mut builder = create_test_builder();
+        builder.set_history(ExampleStates::LevelA1, HistoryMode::Shallow);
+        let hsm = builder.build(ExampleStates::LevelB1).unwrap();
+        let delegate = hsm.get_delegate().upgrade().unwrap();
+        // Nothing remembered yet
+        delegate.change_state(ExampleStates::LevelA1).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA1);
+        delegate.change_state(ExampleStates::LevelA2).unwrap();
+        delegate.change_state(ExampleStates::LevelB1).unwrap();
+        delegate.change_state(ExampleStates::LevelA1).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA2);
+
+        // Shallow history on Top only resumes A1, deep history resumes A2 too
+        for (history_mode, expected_state) in [
+            (HistoryMode::Shallow, ExampleStates::LevelA1),
+            (HistoryMode::Deep, ExampleStates::LevelA2),
+        ] {
+            let mut builder = create_test_builder();
+            builder.set_history(ExampleStates::Top, history_mode);
+            let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+            let delegate = hsm.get_delegate().upgrade().unwrap();
+            delegate.change_state(ExampleStates::LevelB1).unwrap();
+            delegate.change_state(ExampleStates::Top).unwrap();
+            assert_eq!(hsm.get_current_state().unwrap(), expected_state);
+        }
+
+        // Targeting a descendant directly enters it, whatever its ancestors remember
+        let mut builder = create_test_builder();
+        builder.set_history(ExampleStates::Top, HistoryMode::Deep);
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+        let delegate = hsm.get_delegate().upgrade().unwrap();
+        delegate.change_state(ExampleStates::LevelB1).unwrap();
+        delegate.change_state(ExampleStates::LevelA1).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA1);
+
+        let mut builder = create_test_builder();
+        builder.set_history(ExampleStates::LevelB1, HistoryMode::Deep);
+        assert!(matches!(
+            builder.build(ExampleStates::Top),
+            Err(HSMError::InvalidTopology(issues))
+                if issues == vec![TopologyIssue::InvalidHistoryState(ExampleStates::LevelB1)]
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_initial_state() {
+        let mut builder = new_builder();
+        let delegate = builder.get_delegate();
+        builder.add_state(Top::new(delegate), ExampleStates::Top, None);
+        assert!(matches!(
+            builder.build(ExampleStates::LevelA2),
+            Err(HSMError::InvalidStateId(ExampleStates::LevelA2, _, _))
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+//! Records dispatches, handler calls and enter/exit/start hooks with their durations.
+//! Written in the Chrome trace-event JSON format, viewable in Perfetto (ui.perfetto.dev) or chrome://tracing.
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use crate::trace_format::json_string;
+
+/// A completed ("X" phase) span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChromeTraceEvent {
+    pub name: String,
+    /// i.e. dispatch, event, handle_event, enter, exit, start
+    pub category: &'static str,
+    /// Since the recording started
+    pub start: Duration,
+    pub duration: Duration,
+    pub args: Vec<(String, String)>,
+}
+
+/// Everything recorded between HSM::start_chrome_trace and HSM::stop_chrome_trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChromeTrace {
+    pub hsm_name: String,
+    pub events: Vec<ChromeTraceEvent>,
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000_000.0
+}
+
+impl ChromeTrace {
+    pub fn to_json(&self) -> String {
+        let mut trace_events = vec![format!(
+            "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":1,\"args\":{{\"name\":{}}}}}",
+            json_string(&self.hsm_name)
+        )];
+        for event in &self.events {
+            let args: Vec<String> = event
+                .args
+                .iter()
+                .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value)))
+                .collect();
+            trace_events.push(format!(
+                "{{\"name\":{},\"cat\":{},\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":1,\"args\":{{{}}}}}",
+                json_string(&event.name),
+                json_string(event.category),
+                micros(event.start),
+                micros(event.duration),
+                args.join(",")
+            ));
+        }
+        format!(
+            "{{\"traceEvents\":[{}],\"displayTimeUnit\":\"ms\"}}",
+            trace_events.join(",")
+        )
+    }
+
+    /// i.e. to a File to be opened in Perfetto
+    pub fn write_to<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(self.to_json().as_bytes())
+    }
+}
+
+/// Where a span began. The sequence number orders spans that start at the same instant.
+#[derive(Clone, Copy)]
+pub(crate) struct SpanStart {
+    at: Instant,
+    sequence: u64,
+}
+
+pub(crate) struct ChromeTraceRecorder {
+    origin: Instant,
+    next_sequence: u64,
+    /// Along with the sequence number of the span start
+    events: Vec<(u64, ChromeTraceEvent)>,
+}
+
+impl ChromeTraceRecorder {
+    pub(crate) fn new(origin: Instant) -> Self {
+        Self {
+            origin,
+            next_sequence: 0,
+            events: vec![],
+        }
+    }
+
+    pub(crate) fn begin(&mut self, at: Instant) -> SpanStart {
+        self.next_sequence += 1;
+        SpanStart {
+            at,
+            sequence: self.next_sequence,
+        }
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        name: String,
+        category: &'static str,
+        start: SpanStart,
+        end: Instant,
+        args: Vec<(String, String)>,
+    ) {
+        let event = ChromeTraceEvent {
+            name,
+            category,
+            start: start.at.saturating_duration_since(self.origin),
+            duration: end.saturating_duration_since(start.at),
+            args,
+        };
+        self.events.push((start.sequence, event));
+    }
+
+    pub(crate) fn finish(self, hsm_name: String) -> ChromeTrace {
+        // Spans are recorded as they complete (children first). Viewers prefer them in the order they began.
+        let mut events = self.events;
+        events.sort_by_key(|(sequence, _)| *sequence);
+        ChromeTrace {
+            hsm_name,
+            events: events.into_iter().map(|(_, event)| event).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_json() {
+        let origin = Instant::now();
+        let mut recorder = ChromeTraceRecorder::new(origin);
+        let dispatch_start = recorder.begin(origin + Duration::from_micros(10));
+        let exit_start = recorder.begin(origin + Duration::from_micros(15));
+        recorder.record(
+            "LevelA2".to_string(),
+            "exit",
+            exit_start,
+            origin + Duration::from_micros(20),
+            vec![],
+        );
+        recorder.record(
+            "F".to_string(),
+            "dispatch",
+            dispatch_start,
+            origin + Duration::from_micros(30),
+            vec![("state".to_string(), "LevelA2".to_string())],
+        );
+        let trace = recorder.finish("TestHsm".to_string());
+        assert_eq!(trace.events[0].name, "F");
+        assert_eq!(trace.events[0].duration, Duration::from_micros(20));
+
+        assert_eq!(
+            trace.to_json(),
+            concat!(
+                "{\"traceEvents\":[",
+                "{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":1,\"args\":{\"name\":\"TestHsm\"}},",
+                "{\"name\":\"F\",\"cat\":\"dispatch\",\"ph\":\"X\",\"ts\":10.000,\"dur\":20.000,\"pid\":1,\"tid\":1,\"args\":{\"state\":\"LevelA2\"}},",
+                "{\"name\":\"LevelA2\",\"cat\":\"exit\",\"ph\":\"X\",\"ts\":15.000,\"dur\":5.000,\"pid\":1,\"tid\":1,\"args\":{}}",
+                "],\"displayTimeUnit\":\"ms\"}"
+            )
+        );
+
+        let mut written = vec![];
+        trace.write_to(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), trace.to_json());
+    }
+}
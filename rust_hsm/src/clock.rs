@@ -0,0 +1,83 @@
+//! Source of time for the engine. Injectable so time-dependent behavior can be tested deterministically.
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+pub trait HsmClock {
+    /// Monotonic time, for measuring durations
+    fn now(&self) -> Instant;
+    /// Wall-clock time, for timestamps (i.e. of the recent history)
+    fn wall_time(&self) -> SystemTime;
+}
+
+pub type SharedClock = Rc<dyn HsmClock>;
+
+/// Real (monotonic) time. What the engine uses unless told otherwise.
+#[derive(Default)]
+pub struct SystemClock;
+
+impl HsmClock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Time only moves when you tell it to. Useful for tests.
+/// Its wall-clock time starts at the UNIX epoch.
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Cell<Duration>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Cell::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.set(self.elapsed.get() + duration);
+    }
+}
+
+impl HsmClock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + self.elapsed.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+        assert_eq!(
+            clock.wall_time(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(3)
+        );
+    }
+}
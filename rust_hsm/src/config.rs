@@ -0,0 +1,269 @@
+//! Hierarchy described in a config file (TOML, YAML, JSON... any serde format),
+//! so it can be retuned without recompiling.
+//! Names in the config are bound to states, and to their implementations, through a StateRegistry.
+//! Loaded configs go through the same validation as states added by hand (see HsmBuilder::build_from_config).
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{HSMError, HSMResult},
+    events::StateEventConstraint,
+    machine_definition::MachineDefinition,
+    state::{HistoryMode, StateBox, StateConstraint},
+    state_engine_delegate::WeakDelegate,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HsmConfig {
+    /// Name of the state the HSM is built with
+    pub initial_state: String,
+    pub states: Vec<StateConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateConfig {
+    pub name: String,
+    /// None only for the top state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_child: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<HistoryMode>,
+    /// Once any are listed, the state may only change to their targets
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transitions: Vec<TransitionConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransitionConfig {
+    /// Name of the event (see StateEventConstraint::get_event_name)
+    pub event: String,
+    pub target: String,
+}
+
+/// Creates the implementation of a state, given the delegate of the HSM being built
+pub type StateFactory<StateT, EventT> =
+    Box<dyn Fn(WeakDelegate<StateT, EventT>) -> StateBox<StateT, EventT>>;
+
+/// Every state a config may refer to, by name, along with how to create its implementation
+pub struct StateRegistry<StateT: StateConstraint, EventT: StateEventConstraint> {
+    /// name -> (state, factory)
+    states: HashMap<String, (StateT, StateFactory<StateT, EventT>)>,
+}
+
+impl<StateT: StateConstraint, EventT: StateEventConstraint> Default
+    for StateRegistry<StateT, EventT>
+{
+    fn default() -> Self {
+        Self {
+            states: HashMap::new(),
+        }
+    }
+}
+
+impl<StateT: StateConstraint, EventT: StateEventConstraint> StateRegistry<StateT, EventT> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Brief
+    /// Register the state under its Display, replacing whatever was registered under it before.
+    pub fn register<F>(&mut self, state: StateT, create_state: F) -> &mut Self
+    where
+        F: Fn(WeakDelegate<StateT, EventT>) -> StateBox<StateT, EventT> + 'static,
+    {
+        self.states
+            .insert(state.to_string(), (state, Box::new(create_state)));
+        self
+    }
+
+    pub fn get_state(&self, name: &str) -> Option<StateT> {
+        self.states.get(name).map(|(state, _)| *state)
+    }
+
+    /// None if the state was never registered
+    pub(crate) fn create_state(
+        &self,
+        state: StateT,
+        delegate: WeakDelegate<StateT, EventT>,
+    ) -> Option<StateBox<StateT, EventT>> {
+        self.states
+            .get(&state.to_string())
+            .map(|(_, create_state)| create_state(delegate))
+    }
+}
+
+impl HsmConfig {
+    /// # Brief
+    /// Bind every name of the config to its registered state, and check the transition events.
+    /// The topology itself is only validated when building.
+    /// # Arguments
+    /// * `registry` - Every state the config may refer to
+    /// * `event_names` - Every event name transitions may refer to (see StateEventConstraint::get_event_name)
+    /// # Errors
+    /// * UnregisteredStates listing every name no state was registered as
+    /// * Otherwise, UnknownEvents listing every event not among the event names
+    pub fn to_machine_definition<StateT: StateConstraint, EventT: StateEventConstraint>(
+        &self,
+        registry: &StateRegistry<StateT, EventT>,
+        event_names: &[&str],
+    ) -> HSMResult<MachineDefinition<StateT>, StateT> {
+        let mut unregistered: Vec<String> = vec![];
+        let mut resolve = |name: &str| {
+            let state = registry.get_state(name);
+            if state.is_none() && !unregistered.iter().any(|other| other == name) {
+                unregistered.push(name.to_string());
+            }
+            state
+        };
+
+        let mut unknown_events: Vec<String> = vec![];
+
+        let initial_state = resolve(&self.initial_state);
+        let mut states = vec![];
+        let mut initial_children = vec![];
+        let mut history = vec![];
+        let mut transitions = vec![];
+        for state_config in &self.states {
+            let state = resolve(&state_config.name);
+            let parent = state_config.parent.as_deref().map(&mut resolve);
+            if let (Some(state), None | Some(Some(_))) = (state, parent) {
+                states.push((state, parent.flatten()));
+            }
+            let initial_child = state_config.initial_child.as_deref().map(&mut resolve);
+            if let (Some(state), Some(Some(initial_child))) = (state, initial_child) {
+                initial_children.push((state, initial_child));
+            }
+            if let (Some(state), Some(history_mode)) = (state, state_config.history) {
+                history.push((state, history_mode));
+            }
+            for transition in &state_config.transitions {
+                if !event_names.contains(&transition.event.as_str())
+                    && !unknown_events.contains(&transition.event)
+                {
+                    unknown_events.push(transition.event.clone());
+                }
+                if let (Some(state), Some(target)) = (state, resolve(&transition.target)) {
+                    transitions.push((state, transition.event.clone(), target));
+                }
+            }
+        }
+
+        match initial_state {
+            Some(initial_state) if unregistered.is_empty() && unknown_events.is_empty() => {
+                Ok(MachineDefinition {
+                    states,
+                    initial_children,
+                    history,
+                    transitions,
+                    initial_state,
+                })
+            }
+            Some(_) if unregistered.is_empty() => Err(HSMError::UnknownEvents(unknown_events)),
+            _ => Err(HSMError::UnregisteredStates(unregistered)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::HsmBuilder,
+        examples::{A1Impl, B1Impl, ExampleEvents, ExampleFData, ExampleStates, Top},
+        test_utils::ChangingA2,
+        topology_validation::TopologyIssue,
+    };
+    use log::LevelFilter;
+
+    const CONFIG: &str = r#"
+initial_state = "LevelA2"
+
+[[states]]
+name = "Top"
+initial_child = "LevelA1"
+
+[[states]]
+name = "LevelA1"
+parent = "Top"
+history = "deep"
+
+[[states]]
+name = "LevelA2"
+parent = "LevelA1"
+transitions = [{ event = "F", target = "LevelB1" }]
+
+[[states]]
+name = "LevelB1"
+parent = "Top"
+transitions = [{ event = "A", target = "LevelA1" }]
+"#;
+
+    fn create_test_registry() -> StateRegistry<ExampleStates, ExampleEvents> {
+        let mut registry = StateRegistry::new();
+        registry
+            .register(ExampleStates::Top, |delegate| Top::new(delegate))
+            .register(ExampleStates::LevelA1, |delegate| A1Impl::new(delegate))
+            .register(ExampleStates::LevelB1, |delegate| B1Impl::new(delegate))
+            .register(ExampleStates::LevelA2, |delegate| ChangingA2::new(delegate));
+        registry
+    }
+
+    const EVENT_NAMES: [&str; 6] = ["A", "B", "C", "D", "E", "F"];
+
+    fn create_empty_builder() -> HsmBuilder<ExampleStates, ExampleEvents> {
+        HsmBuilder::new("TestHsm".to_string(), LevelFilter::Info)
+    }
+
+    #[test]
+    fn build_from_config() {
+        let config: HsmConfig = toml::from_str(CONFIG).unwrap();
+        let hsm = create_empty_builder()
+            .build_from_config(&config, &create_test_registry(), &EVENT_NAMES)
+            .unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA2);
+        assert_eq!(
+            hsm.transition_registry()
+                .events(ExampleStates::LevelA2, ExampleStates::LevelB1),
+            ["F"]
+        );
+
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelB1);
+        // Deep history on LevelA1 resumes LevelA2
+        let delegate = hsm.get_delegate().upgrade().unwrap();
+        delegate.change_state(ExampleStates::LevelA1).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA2);
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        let mut config: HsmConfig = toml::from_str(CONFIG).unwrap();
+        config.states[1].parent = Some("LevelC1".to_string());
+        config.states[2].transitions[0].target = "LevelC2".to_string();
+        assert!(matches!(
+            create_empty_builder().build_from_config(&config, &create_test_registry(), &EVENT_NAMES),
+            Err(HSMError::UnregisteredStates(names)) if names == ["LevelC1", "LevelC2"]
+        ));
+
+        // Same validation as states added by hand
+        let mut config: HsmConfig = toml::from_str(CONFIG).unwrap();
+        config.states[0].parent = Some("LevelA2".to_string());
+        assert!(matches!(
+            create_empty_builder().build_from_config(&config, &create_test_registry(), &EVENT_NAMES),
+            Err(HSMError::InvalidTopology(issues))
+                if issues[0] == TopologyIssue::NoRoot
+        ));
+
+        let mut config: HsmConfig = toml::from_str(CONFIG).unwrap();
+        config.states[2].transitions[0].event = "G".to_string();
+        config.states[3].transitions[0].event = "H".to_string();
+        assert!(matches!(
+            create_empty_builder().build_from_config(&config, &create_test_registry(), &EVENT_NAMES),
+            Err(HSMError::UnknownEvents(events)) if events == ["G", "H"]
+        ));
+    }
+}
@@ -0,0 +1,87 @@
+//! Structured record of everything the engine did while handling a dispatched event.
+//! Lets consumers (and tests!) assert on data instead of parsing log text.
+use std::fmt::Display;
+
+use crate::{sequence_diagram_export::to_sequence_diagram, state_diagram_export::DiagramDialect};
+
+/// The answer a state gave when the event was offered to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateOffer<StateT> {
+    pub state: StateT,
+    /// True if the state handled the event and stopped it bubbling up to its parent.
+    pub handled: bool,
+}
+
+/// What happened to a state while transitioning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionAction {
+    Exit,
+    Enter,
+    Start,
+}
+
+/// A single exit / enter / start performed by the engine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransitionStep<StateT> {
+    pub state: StateT,
+    pub action: TransitionAction,
+}
+
+/// # Brief
+/// Report of how a single event was handled.
+/// The report returned by `HSM::dispatch_event_with_report` is for the dispatched event.
+/// Any events states fired back at the engine while handling it are in `internal_events`
+/// (in the order they were handled).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DispatchReport<StateT> {
+    pub event_name: String,
+    /// State the event was first offered to.
+    pub start_state: StateT,
+    /// Every state the event was offered to (leaf -> root) and their answer.
+    pub offers: Vec<StateOffer<StateT>>,
+    /// None if no state handled the event.
+    pub handled_by: Option<StateT>,
+    /// Every exit / enter / start, in the order they were performed.
+    pub steps: Vec<TransitionStep<StateT>>,
+    pub internal_events: Vec<DispatchReport<StateT>>,
+    /// Current state once the event (and its internal events) were handled.
+    pub final_state: StateT,
+}
+
+impl<StateT: Clone> DispatchReport<StateT> {
+    pub(crate) fn new(event_name: String, start_state: StateT) -> Self {
+        Self {
+            event_name,
+            start_state: start_state.clone(),
+            offers: vec![],
+            handled_by: None,
+            steps: vec![],
+            internal_events: vec![],
+            final_state: start_state,
+        }
+    }
+
+    /// States with the given action performed on them (in order).
+    pub fn states_with_action(&self, action: TransitionAction) -> Vec<StateT> {
+        self.steps
+            .iter()
+            .filter(|step| step.action == action)
+            .map(|step| step.state.clone())
+            .collect()
+    }
+}
+
+impl<StateT: Display + PartialEq> DispatchReport<StateT> {
+    /// # Brief
+    /// PlantUML sequence diagram of the report (i.e. to attach to a bug report).
+    /// One lifeline for the engine and one per state offered the event or transitioned.
+    pub fn to_plantuml(&self) -> String {
+        to_sequence_diagram(self, DiagramDialect::PlantUml)
+    }
+
+    /// # Brief
+    /// Same as to_plantuml, as a Mermaid sequence diagram
+    pub fn to_mermaid(&self) -> String {
+        to_sequence_diagram(self, DiagramDialect::Mermaid)
+    }
+}
@@ -0,0 +1,161 @@
+//! Graphviz (DOT) rendering of an HSM, for design reviews and documentation.
+//! Composite states are drawn as clusters holding their children, leaves as nodes.
+//! Declared transitions (see TransitionRegistry) are the edges, labelled with their events.
+use std::fmt::Write;
+
+use crate::{events::StateEventConstraint, state::StateConstraint, state_mapping::StateMapping};
+
+const HIGHLIGHT_COLOR: &str = "lightblue";
+
+/// # Brief
+/// Render the states and declared transitions of the mapping as a DOT digraph.
+/// # Arguments
+/// * `highlighted_state` - State to fill in (i.e. the current state), if any
+pub(crate) fn to_dot<StateT: StateConstraint, EventT: StateEventConstraint>(
+    state_mapping: &StateMapping<StateT, EventT>,
+    highlighted_state: Option<StateT>,
+) -> String {
+    let mut dot =
+        String::from("digraph {\n    compound=true;\n    node [shape=box, style=rounded];\n");
+    for state in state_mapping.get_all_state_ids() {
+        if state_mapping.get_parent_state_id(&state).is_none() {
+            write_state(&mut dot, state_mapping, state, highlighted_state, 1);
+        }
+    }
+
+    let transitions = state_mapping.get_transitions();
+    for (source, target) in transitions.edges() {
+        let mut attributes = vec![];
+        let events = transitions.events(source, target);
+        if !events.is_empty() {
+            attributes.push(format!("label={}", quoted(&events.join(", "))));
+        }
+        // Edges can only join nodes, clusters are pointed at through their anchor
+        if is_composite(state_mapping, source) {
+            attributes.push(format!("ltail={}", cluster_name(source)));
+        }
+        if is_composite(state_mapping, target) {
+            attributes.push(format!("lhead={}", cluster_name(target)));
+        }
+        let _ = write!(dot, "    {} -> {}", quoted(&source), quoted(&target));
+        if !attributes.is_empty() {
+            let _ = write!(dot, " [{}]", attributes.join(", "));
+        }
+        dot.push_str(";\n");
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn write_state<StateT: StateConstraint, EventT: StateEventConstraint>(
+    dot: &mut String,
+    state_mapping: &StateMapping<StateT, EventT>,
+    state: StateT,
+    highlighted_state: Option<StateT>,
+    depth: usize,
+) {
+    let indent = "    ".repeat(depth);
+    let highlighted = highlighted_state == Some(state);
+    let children = state_mapping.get_children_state_ids(&state);
+    if children.is_empty() {
+        let _ = write!(dot, "{}{}", indent, quoted(&state));
+        if highlighted {
+            let _ = write!(
+                dot,
+                " [style=\"rounded,filled\", fillcolor={}]",
+                HIGHLIGHT_COLOR
+            );
+        }
+        dot.push_str(";\n");
+        return;
+    }
+
+    let _ = writeln!(dot, "{}subgraph {} {{", indent, cluster_name(state));
+    let _ = writeln!(dot, "{}    label={};", indent, quoted(&state));
+    if highlighted {
+        let _ = writeln!(dot, "{}    style=filled;", indent);
+        let _ = writeln!(dot, "{}    fillcolor={};", indent, HIGHLIGHT_COLOR);
+    }
+    // Anchor for the transitions to and from the cluster
+    let _ = writeln!(
+        dot,
+        "{}    {} [shape=point, style=invis];",
+        indent,
+        quoted(&state)
+    );
+    for child in children {
+        write_state(dot, state_mapping, child, highlighted_state, depth + 1);
+    }
+    let _ = writeln!(dot, "{}}}", indent);
+}
+
+fn is_composite<StateT: StateConstraint, EventT: StateEventConstraint>(
+    state_mapping: &StateMapping<StateT, EventT>,
+    state: StateT,
+) -> bool {
+    !state_mapping.get_children_state_ids(&state).is_empty()
+}
+
+fn cluster_name<StateT: StateConstraint>(state: StateT) -> String {
+    quoted(&format!("cluster_{}", state))
+}
+
+/// Backslashes first, so a trailing one can not escape the closing quote
+fn quoted(id: &impl ToString) -> String {
+    format!(
+        "\"{}\"",
+        id.to_string().replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{examples::ExampleStates, test_utils::create_test_builder};
+
+    #[test]
+    fn to_dot() {
+        let mut builder = create_test_builder();
+        builder
+            .add_transition_event(ExampleStates::LevelA2, "F", ExampleStates::LevelB1)
+            .add_transition_event(ExampleStates::LevelA2, "C", ExampleStates::LevelB1)
+            .add_transition_event(ExampleStates::LevelB1, "A", ExampleStates::LevelA1);
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+
+        assert_eq!(
+            hsm.to_dot(true).unwrap(),
+            r#"digraph {
+    compound=true;
+    node [shape=box, style=rounded];
+    subgraph "cluster_Top" {
+        label="Top";
+        "Top" [shape=point, style=invis];
+        subgraph "cluster_LevelA1" {
+            label="LevelA1";
+            "LevelA1" [shape=point, style=invis];
+            "LevelA2" [style="rounded,filled", fillcolor=lightblue];
+        }
+        "LevelB1";
+    }
+    "LevelA2" -> "LevelB1" [label="F, C"];
+    "LevelB1" -> "LevelA1" [label="A", lhead="cluster_LevelA1"];
+}
+"#
+        );
+        assert!(!hsm.to_dot(false).unwrap().contains("filled"));
+    }
+
+    #[test]
+    fn escapes_quotes_and_backslashes() {
+        let mut builder = create_test_builder();
+        builder.add_transition_event(
+            ExampleStates::LevelA2,
+            r#"Say "hi" \"#,
+            ExampleStates::LevelB1,
+        );
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+        assert!(hsm
+            .to_dot(false)
+            .unwrap()
+            .contains(r#""LevelA2" -> "LevelB1" [label="Say \"hi\" \\"];"#));
+    }
+}
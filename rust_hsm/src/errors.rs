@@ -1,13 +1,21 @@
 use thiserror::Error;
 
+#[cfg(feature = "scxml")]
+use crate::scxml::ScxmlIssue;
+use crate::{flight_recorder::HistoryEntry, topology_validation::TopologyIssue};
+
 // pub type HSMResult<T> = std::result::Result<T, HSMError>;
 pub type HSMResult<T, States> = std::result::Result<T, HSMError<States>>;
 
+/// Recent history of the engine (oldest -> newest) when an error was raised while dispatching.
+/// Empty when raised outside of dispatching (i.e. while building).
+pub type ErrorHistory<StateT> = Vec<HistoryEntry<StateT>>;
+
 // https://stackoverflow.com/a/62101140/14810215
+/// Non exhaustive, as some variants only exist with their feature (i.e. InvalidScxml with scxml)
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum HSMError<StateT> {
-    #[error("State {0} with id {1} already added, but is getting added again!")]
-    AddDuplicateStateId(StateT, u16),
     #[error("Delegate upgrade failed! was this function called while the EngineDelegate was being destroyed? Context: {0}")]
     DelegateUpgradeFail(String),
     #[error("Event Not Implemented Error: {0}")]
@@ -19,13 +27,76 @@ pub enum HSMError<StateT> {
     #[error("Expected State {0} to have parent state with id {1}. But it was never added to controller! Should be impossible")]
     ImpossibleStateMismatch(StateT, StateT),
     #[error("State {0} never added to controller! But requested by {1}!")]
-    InvalidStateId(StateT, String),
+    InvalidStateId(StateT, String, ErrorHistory<StateT>),
+    #[error("State {0} is not allowed to change state to {1}")]
+    IllegalTransition(StateT, StateT, ErrorHistory<StateT>),
+    /// Every problem found with the states and their parents when building the HSM
+    #[error("Invalid HSM topology: {}", .0.iter().map(ToString::to_string).collect::<Vec<String>>().join("; "))]
+    InvalidTopology(Vec<TopologyIssue<StateT>>),
+    /// Every problem found while loading (or writing) an SCXML document
+    #[cfg(feature = "scxml")]
+    #[error("Invalid SCXML: {}", .0.iter().map(ToString::to_string).collect::<Vec<String>>().join("; "))]
+    InvalidScxml(Vec<ScxmlIssue>),
+    /// Names used by a config which no state was registered as
+    #[cfg(feature = "serde")]
+    #[error("No state registered as: {}", .0.join(", "))]
+    UnregisteredStates(Vec<String>),
+    /// Events used by a config which are not among the event names
+    #[cfg(feature = "serde")]
+    #[error("No event named: {}", .0.join(", "))]
+    UnknownEvents(Vec<String>),
     #[error("You asked for the LCA between the same nodes!")]
     LCAOfSameNode(),
-    #[error("Error validating the relationship between states: {0}")]
-    MapValidationError(String),
     #[error("Requesting change state to {0}, but there was already a change state request to {1} while handling {2}" )]
-    MultipleConcurrentChangeState(StateT, StateT, String),
-    #[error("Reserved State {0} with id {1} as Top, but then added state {2} with id {3} without parents")]
-    MultipleTopState(String, u16, String, u16),
+    MultipleConcurrentChangeState(StateT, StateT, String, ErrorHistory<StateT>),
+}
+
+impl<StateT> HSMError<StateT> {
+    /// Name of the variant, without any of its data. Handy as a label (i.e. metrics).
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            HSMError::DelegateUpgradeFail(..) => "DelegateUpgradeFail",
+            HSMError::EventNotImplemented(..) => "EventNotImplemented",
+            HSMError::EngineNotInitialized() => "EngineNotInitialized",
+            HSMError::GenericError(..) => "GenericError",
+            HSMError::ImpossibleStateMismatch(..) => "ImpossibleStateMismatch",
+            HSMError::InvalidStateId(..) => "InvalidStateId",
+            HSMError::IllegalTransition(..) => "IllegalTransition",
+            HSMError::InvalidTopology(..) => "InvalidTopology",
+            #[cfg(feature = "scxml")]
+            HSMError::InvalidScxml(..) => "InvalidScxml",
+            #[cfg(feature = "serde")]
+            HSMError::UnregisteredStates(..) => "UnregisteredStates",
+            #[cfg(feature = "serde")]
+            HSMError::UnknownEvents(..) => "UnknownEvents",
+            HSMError::LCAOfSameNode() => "LCAOfSameNode",
+            HSMError::MultipleConcurrentChangeState(..) => "MultipleConcurrentChangeState",
+        }
+    }
+
+    /// Recent history of the engine when the error was raised while dispatching (see ErrorHistory).
+    /// Always empty for the variants raised outside of dispatching.
+    pub fn history(&self) -> &[HistoryEntry<StateT>] {
+        match self {
+            HSMError::InvalidStateId(_, _, history)
+            | HSMError::IllegalTransition(_, _, history)
+            | HSMError::MultipleConcurrentChangeState(_, _, _, history) => history,
+            _ => &[],
+        }
+    }
+
+    /// Attach the history, unless the error already has some (i.e. it was raised by a nested dispatch)
+    pub(crate) fn with_history(mut self, recent_history: ErrorHistory<StateT>) -> Self {
+        match &mut self {
+            HSMError::InvalidStateId(_, _, history)
+            | HSMError::IllegalTransition(_, _, history)
+            | HSMError::MultipleConcurrentChangeState(_, _, _, history)
+                if history.is_empty() =>
+            {
+                *history = recent_history;
+            }
+            _ => {}
+        }
+        self
+    }
 }
@@ -1,47 +1,18 @@
 //! Contains structs and infrastructure useful for minimal examples in docs (and tests)
-use crate::{
-    events::StateEventConstraint,
-    state::{StateConstraint, StateIF, StateId},
-    state_engine_delegate::WeakDelegate,
-};
+use crate::{state::StateIF, state_engine_delegate::WeakDelegate, HsmEvent, HsmState};
 
 use std::cell::RefCell;
 
 // Start of States Enum //
 
-#[repr(u16)]
-#[derive(strum::FromRepr, Clone, PartialEq, Debug, strum::Display)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, strum::Display, HsmState)]
 pub enum ExampleStates {
-    INVALID = u16::MAX,
-    Top = 1,
-    LevelA1 = 2,
-    LevelB1 = 3,
-    LevelA2 = 4,
+    Top,
+    LevelA1,
+    LevelB1,
+    LevelA2,
 }
 
-impl From<ExampleStates> for u16 {
-    fn from(val: ExampleStates) -> Self {
-        val as u16
-    }
-}
-
-impl From<ExampleStates> for StateId {
-    fn from(val: ExampleStates) -> Self {
-        StateId::new(val as u16)
-    }
-}
-
-impl From<u16> for ExampleStates {
-    fn from(state_id: u16) -> Self {
-        match Self::from_repr(state_id) {
-            Some(val) => val,
-            None => Self::INVALID,
-        }
-    }
-}
-
-impl StateConstraint for ExampleStates {}
-
 // End of States Enum //
 
 // Start of States Data //
@@ -107,7 +78,7 @@ pub struct ExampleFData {
     x: i32,
 }
 
-#[derive(Debug, strum::Display)]
+#[derive(Debug, strum::Display, HsmEvent)]
 pub enum ExampleEvents {
     A,
     B(u8),
@@ -120,8 +91,6 @@ pub enum ExampleEvents {
     Invalid,
 }
 
-impl StateEventConstraint for ExampleEvents {}
-
 // End of Example Event //
 
 // Start of State Impl //
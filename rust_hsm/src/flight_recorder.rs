@@ -0,0 +1,116 @@
+//! Always-on, fixed size history of the most recent events handled by the engine.
+//! When something goes wrong in the field, this is how we find out how the machine got there.
+use std::{collections::VecDeque, time::SystemTime};
+
+/// How many events are remembered unless told otherwise.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 32;
+
+/// A change of state performed while handling an event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedTransition<StateT> {
+    pub source: StateT,
+    pub target: StateT,
+}
+
+/// Everything remembered about a single handled event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry<StateT> {
+    pub event_name: String,
+    /// State the event was first offered to
+    pub source_state: StateT,
+    /// None if no state handled the event (or handling never completed)
+    pub handled_by: Option<StateT>,
+    pub transitions: Vec<RecordedTransition<StateT>>,
+    pub started_at: SystemTime,
+    /// None if handling the event never completed (i.e. it errored)
+    pub completed_at: Option<SystemTime>,
+}
+
+/// Ring buffer of HistoryEntry's. Oldest entries are dropped once full.
+pub(crate) struct FlightRecorder<StateT> {
+    capacity: usize,
+    entries: VecDeque<HistoryEntry<StateT>>,
+}
+
+impl<StateT: Clone> FlightRecorder<StateT> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Start remembering a new event, handled from now on
+    pub(crate) fn record_event(
+        &mut self,
+        event_name: String,
+        source_state: StateT,
+        now: SystemTime,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(HistoryEntry {
+            event_name,
+            source_state,
+            handled_by: None,
+            transitions: vec![],
+            started_at: now,
+            completed_at: None,
+        });
+    }
+
+    /// Modify the entry of the event currently being handled
+    pub(crate) fn update_current<F: FnOnce(&mut HistoryEntry<StateT>)>(&mut self, update: F) {
+        if let Some(entry) = self.entries.back_mut() {
+            update(entry);
+        }
+    }
+
+    /// Oldest -> newest
+    pub(crate) fn entries(&self) -> Vec<HistoryEntry<StateT>> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_entries_dropped() {
+        let now = SystemTime::UNIX_EPOCH;
+        let mut recorder = FlightRecorder::<u16>::new(2);
+        recorder.record_event("A".to_string(), 1, now);
+        recorder.update_current(|entry| entry.handled_by = Some(1));
+        recorder.record_event("B".to_string(), 1, now);
+        recorder.record_event("C".to_string(), 2, now);
+
+        let entries = recorder.entries();
+        let names: Vec<&str> = entries
+            .iter()
+            .map(|entry| entry.event_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["B", "C"]);
+        assert_eq!(entries[1].source_state, 2);
+        assert!(entries[1].handled_by.is_none());
+
+        recorder.set_capacity(1);
+        assert_eq!(recorder.entries().len(), 1);
+        assert_eq!(recorder.entries()[0].event_name, "C");
+
+        let mut disabled = FlightRecorder::<u16>::new(0);
+        disabled.record_event("A".to_string(), 1, now);
+        assert!(disabled.entries().is_empty());
+    }
+}
@@ -1,11 +1,38 @@
+// Lets the derive macros refer to ::rust_hsm from within this crate too
+extern crate self as rust_hsm;
+
+pub use rust_hsm_derive::{hsm_states, HsmEvent, HsmState};
+
+pub mod builder;
+pub mod chrome_trace;
+pub mod clock;
+#[cfg(feature = "serde")]
+pub mod config;
+pub mod dispatch_report;
+mod dot_export;
 pub mod errors;
 pub mod events;
 pub mod examples;
+pub mod flight_recorder;
+pub mod log_sink;
 pub mod logger;
+pub mod machine_definition;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod observer;
+pub mod residency;
+#[cfg(feature = "scxml")]
+pub mod scxml;
+mod sequence_diagram_export;
 pub mod state;
+mod state_diagram_export;
 pub mod state_engine;
 pub mod state_engine_delegate;
 mod state_mapping;
+pub mod topology_validation;
+pub mod trace_format;
+pub mod transition_registry;
+pub mod typed_transitions;
 mod utils;
 
 #[cfg(test)]
@@ -0,0 +1,121 @@
+//! Destinations the HSMLogger can write dispatch traces to (in addition to the `log` facade).
+use std::{cell::RefCell, collections::VecDeque, io::Write, rc::Rc};
+
+/// Something that accepts fully formatted trace lines.
+pub trait LogSink {
+    fn write_line(&self, line: &str);
+}
+
+pub type SharedLogSink = Rc<dyn LogSink>;
+
+/// Writes every line (newline terminated) to anything implementing io::Write.
+/// i.e. files, stdout, `Vec<u8>`
+pub struct WriterSink<W: Write> {
+    writer: RefCell<W>,
+}
+
+impl<W: Write> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: RefCell::new(writer),
+        }
+    }
+
+    /// Give back the writer. i.e. to inspect what was written to a buffer.
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner()
+    }
+}
+
+impl<W: Write> LogSink for WriterSink<W> {
+    fn write_line(&self, line: &str) {
+        // A sink failing should never take down the hsm
+        let _ = writeln!(self.writer.borrow_mut(), "{}", line);
+    }
+}
+
+/// Keeps the most recent lines in memory. Oldest lines are dropped once full.
+pub struct RingBufferSink {
+    capacity: usize,
+    lines: RefCell<VecDeque<String>>,
+}
+
+impl RingBufferSink {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lines: RefCell::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Oldest -> newest
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.borrow().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.lines.borrow_mut().clear();
+    }
+}
+
+impl LogSink for RingBufferSink {
+    fn write_line(&self, line: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lines = self.lines.borrow_mut();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line.to_string());
+    }
+}
+
+/// Hands every line to a closure.
+pub struct ClosureSink<F: Fn(&str)> {
+    closure: F,
+}
+
+impl<F: Fn(&str)> ClosureSink<F> {
+    pub fn new(closure: F) -> Self {
+        Self { closure }
+    }
+}
+
+impl<F: Fn(&str)> LogSink for ClosureSink<F> {
+    fn write_line(&self, line: &str) {
+        (self.closure)(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writer_sink() {
+        let sink = WriterSink::new(Vec::<u8>::new());
+        sink.write_line("first");
+        sink.write_line("second");
+        assert_eq!(sink.into_inner(), b"first\nsecond\n");
+    }
+
+    #[test]
+    fn ring_buffer_sink() {
+        let sink = RingBufferSink::new(2);
+        sink.write_line("first");
+        sink.write_line("second");
+        sink.write_line("third");
+        assert_eq!(sink.lines(), vec!["second", "third"]);
+        sink.clear();
+        assert!(sink.lines().is_empty());
+    }
+
+    #[test]
+    fn closure_sink() {
+        let written = RefCell::new(vec![]);
+        let sink = ClosureSink::new(|line: &str| written.borrow_mut().push(line.to_string()));
+        sink.write_line("first");
+        assert_eq!(written.take(), vec!["first"]);
+    }
+}
@@ -1,56 +1,117 @@
 //! Encapsulates how information should be logged!
+//! Everything is emitted through the `log` facade, so whatever logger your application
+//! installs decides where (and if) messages end up.
+//! Dispatch traces can additionally be written to any number of sinks in the chosen format.
 use log::LevelFilter;
 
+use crate::{
+    log_sink::SharedLogSink,
+    trace_format::{TraceFormat, TraceRecord},
+};
+
+/// Target used when the logger is not tied to a specific HSM.
+const DEFAULT_LOG_TARGET: &str = "rust_hsm";
+
 #[derive(Clone)]
 /// Logger for the hsm!
 pub struct HSMLogger {
+    /// Caps the levels emitted by this hsm on top of the `log` facade's own filtering
     pub(crate) log_level_allowed: log::LevelFilter,
+    /// Target handed to the `log` facade. Lets applications filter per hsm.
+    pub(crate) target: String,
+    /// Format dispatch traces are written in (to both the facade and sinks)
+    pub(crate) trace_format: TraceFormat,
+    /// Every dispatch trace is also written to these
+    pub(crate) sinks: Vec<SharedLogSink>,
 }
 
 impl Default for HSMLogger {
     fn default() -> Self {
         Self {
             log_level_allowed: log::LevelFilter::Info,
+            target: DEFAULT_LOG_TARGET.to_string(),
+            trace_format: Default::default(),
+            sinks: vec![],
         }
     }
 }
 
 impl HSMLogger {
     /// # Params
-    /// level_allowed - The level of logs that will actually be printed
+    /// level_allowed - The level of logs that will actually be forwarded to the `log` facade
     pub fn new(level_allowed: log::LevelFilter) -> Self {
         Self {
             log_level_allowed: level_allowed,
+            target: DEFAULT_LOG_TARGET.to_string(),
+            trace_format: Default::default(),
+            sinks: vec![],
         }
     }
 
-    fn log_msg(&self, log_requested: &log::LevelFilter, function_logging: String, msg: &str) {
-        if log_requested <= &self.log_level_allowed {
-            println!("[{}][{}] {}", log_requested.as_str(), function_logging, msg);
+    /// Tie the logger to an hsm. Logs will use the target `rust_hsm::<hsm_name>`.
+    pub fn with_hsm_name(mut self, hsm_name: &str) -> Self {
+        self.target = format!("{}::{}", DEFAULT_LOG_TARGET, hsm_name);
+        self
+    }
+
+    /// Choose how dispatch traces are rendered.
+    pub fn with_trace_format(mut self, trace_format: TraceFormat) -> Self {
+        self.trace_format = trace_format;
+        self
+    }
+
+    /// Add a sink that receives every dispatch trace (formatted). Sinks ignore the level filter.
+    pub fn with_sink(mut self, sink: SharedLogSink) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn get_target(&self) -> &str {
+        self.target.as_str()
+    }
+
+    fn log_msg(&self, log_requested: log::Level, function_logging: String, msg: &str) {
+        if log_requested <= self.log_level_allowed {
+            log::log!(
+                target: self.target.as_str(),
+                log_requested,
+                "[{}] {}",
+                function_logging,
+                msg
+            );
         }
     }
 
-    /// Attempt to log an info msg. It will get printed conditionally based on
+    /// Attempt to log an info msg. It will get emitted conditionally based on
     /// how you init the logger
     #[allow(dead_code)]
     pub(crate) fn log_info(&self, function_logging: String, msg: &str) {
-        self.log_msg(&log::LevelFilter::Info, function_logging, msg)
+        self.log_msg(log::Level::Info, function_logging, msg)
     }
 
-    /// Attempt to log an error msg. It will get printed conditionally based on
+    /// Attempt to log an error msg. It will get emitted conditionally based on
     /// how you init the logger
     pub(crate) fn log_error(&self, function_logging: String, msg: &str) {
-        self.log_msg(&log::LevelFilter::Error, function_logging, msg)
+        self.log_msg(log::Level::Error, function_logging, msg)
     }
 
-    /// Attempt to log debug msg. It will get printed conditionally based on
+    /// Attempt to log debug msg. It will get emitted conditionally based on
     /// how you init the logger.
     pub(crate) fn log_debug(&self, function_logging: String, msg: &str) {
-        self.log_msg(&log::LevelFilter::Debug, function_logging, msg)
+        self.log_msg(log::Level::Debug, function_logging, msg)
     }
 
     pub(crate) fn log_trace(&self, function_logging: String, msg: &str) {
-        self.log_msg(&log::LevelFilter::Trace, function_logging, msg)
+        self.log_msg(log::Level::Trace, function_logging, msg)
+    }
+
+    /// Log how an event was handled (as info) and write it to every sink.
+    pub(crate) fn log_dispatch_trace(&self, function_logging: String, record: &TraceRecord) {
+        let line = self.trace_format.format(record);
+        self.log_info(function_logging, line.as_str());
+        for sink in &self.sinks {
+            sink.write_line(line.as_str());
+        }
     }
 }
 
@@ -59,3 +120,18 @@ impl From<LevelFilter> for HSMLogger {
         HSMLogger::new(level)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_derived_from_hsm_name() {
+        let logger = HSMLogger::new(LevelFilter::Debug);
+        assert_eq!(logger.get_target(), "rust_hsm");
+
+        let logger = logger.with_hsm_name("LightControllerHsm");
+        assert_eq!(logger.get_target(), "rust_hsm::LightControllerHsm");
+        assert_eq!(logger.log_level_allowed, LevelFilter::Debug);
+    }
+}
@@ -0,0 +1,17 @@
+//! Topology of an HSM as plain data, i.e. loaded from a chart authored outside of Rust.
+//! Holds no behavior: the states themselves are still implemented in Rust and
+//! handed over alongside it (see HsmBuilder::add_machine_definition).
+use crate::state::{HistoryMode, StateConstraint};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineDefinition<StateT: StateConstraint> {
+    /// Every state with its parent (None only for the top state), parents first
+    pub states: Vec<(StateT, Option<StateT>)>,
+    /// (parent, initial child) pairs
+    pub initial_children: Vec<(StateT, StateT)>,
+    pub history: Vec<(StateT, HistoryMode)>,
+    /// (source, event name, target) triples
+    pub transitions: Vec<(StateT, String, StateT)>,
+    /// State the HSM is built with
+    pub initial_state: StateT,
+}
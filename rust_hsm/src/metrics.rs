@@ -0,0 +1,331 @@
+//! Metrics collected from the engine's dispatch path (only with the `metrics` feature).
+//! Snapshots render to the Prometheus text exposition format so they can be served as-is.
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::{Display, Write},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{SharedClock, SystemClock},
+    errors::HSMError,
+    events::StateEventConstraint,
+    observer::HsmObserver,
+};
+
+/// Upper bounds (in seconds) of the time-in-state histogram buckets. +Inf is implied.
+pub const TIME_IN_STATE_BUCKETS: [f64; 10] =
+    [0.001, 0.01, 0.1, 0.5, 1.0, 5.0, 30.0, 60.0, 300.0, 3600.0];
+
+/// Distribution of how long the machine stayed in a state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Count of observations <= the bucket with the same index in TIME_IN_STATE_BUCKETS
+    pub bucket_counts: Vec<u64>,
+    pub sum_seconds: f64,
+    pub count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            bucket_counts: vec![0; TIME_IN_STATE_BUCKETS.len()],
+            sum_seconds: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        for (bucket, upper_bound) in self.bucket_counts.iter_mut().zip(TIME_IN_STATE_BUCKETS) {
+            if seconds <= upper_bound {
+                *bucket += 1;
+            }
+        }
+        self.sum_seconds += seconds;
+        self.count += 1;
+    }
+}
+
+/// Point in time copy of everything collected. States/events are keyed by their display names.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    pub hsm_name: String,
+    /// event -> count
+    pub dispatches: BTreeMap<String, u64>,
+    /// state -> count of events it handled
+    pub handled_by_state: BTreeMap<String, u64>,
+    /// event -> count of times no state handled it
+    pub unhandled: BTreeMap<String, u64>,
+    /// (source, target) -> count
+    pub transitions: BTreeMap<(String, String), u64>,
+    /// HSMError variant -> count
+    pub errors: BTreeMap<String, u64>,
+    /// state -> how long it was the current state (only once it has been left)
+    pub time_in_state: BTreeMap<String, Histogram>,
+}
+
+/// Escape a label value per the Prometheus text format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl MetricsSnapshot {
+    /// Render in the Prometheus text exposition format (version 0.0.4)
+    pub fn to_prometheus(&self) -> String {
+        let hsm = escape_label(&self.hsm_name);
+        let mut out = String::new();
+
+        write_counter_family(
+            &mut out,
+            "rust_hsm_dispatches_total",
+            "Events dispatched into the hsm",
+            self.dispatches
+                .iter()
+                .map(|(event, count)| (format!("event=\"{}\"", escape_label(event)), *count)),
+            &hsm,
+        );
+        write_counter_family(
+            &mut out,
+            "rust_hsm_handled_total",
+            "Events handled, by the state that handled them",
+            self.handled_by_state
+                .iter()
+                .map(|(state, count)| (format!("state=\"{}\"", escape_label(state)), *count)),
+            &hsm,
+        );
+        write_counter_family(
+            &mut out,
+            "rust_hsm_unhandled_total",
+            "Events no state handled",
+            self.unhandled
+                .iter()
+                .map(|(event, count)| (format!("event=\"{}\"", escape_label(event)), *count)),
+            &hsm,
+        );
+        write_counter_family(
+            &mut out,
+            "rust_hsm_transitions_total",
+            "Changes of state",
+            self.transitions.iter().map(|((source, target), count)| {
+                (
+                    format!(
+                        "source=\"{}\",target=\"{}\"",
+                        escape_label(source),
+                        escape_label(target)
+                    ),
+                    *count,
+                )
+            }),
+            &hsm,
+        );
+        write_counter_family(
+            &mut out,
+            "rust_hsm_errors_total",
+            "Errors raised, by HSMError variant",
+            self.errors
+                .iter()
+                .map(|(error, count)| (format!("error=\"{}\"", escape_label(error)), *count)),
+            &hsm,
+        );
+
+        let name = "rust_hsm_time_in_state_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Time spent in a state before leaving it",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (state, histogram) in &self.time_in_state {
+            let labels = format!("hsm=\"{}\",state=\"{}\"", hsm, escape_label(state));
+            for (upper_bound, count) in TIME_IN_STATE_BUCKETS.iter().zip(&histogram.bucket_counts) {
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{{},le=\"{}\"}} {}",
+                    name, labels, upper_bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"+Inf\"}} {}",
+                name, labels, histogram.count
+            );
+            let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum_seconds);
+            let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+        }
+        out
+    }
+}
+
+fn write_counter_family<I: Iterator<Item = (String, u64)>>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    samples: I,
+    hsm: &str,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for (labels, count) in samples {
+        let _ = writeln!(out, "{}{{hsm=\"{}\",{}}} {}", name, hsm, labels, count);
+    }
+}
+
+/// # Brief
+/// Collects metrics by observing the engine.
+/// Every HSM owns one (with the `metrics` feature) - see HSM::metrics_snapshot.
+pub struct HsmMetrics {
+    snapshot: RefCell<MetricsSnapshot>,
+    /// The current state and when it was started
+    current_state: RefCell<Option<(String, Instant)>>,
+    clock: RefCell<SharedClock>,
+}
+
+impl HsmMetrics {
+    pub fn new(hsm_name: &str) -> Self {
+        Self {
+            snapshot: RefCell::new(MetricsSnapshot {
+                hsm_name: hsm_name.to_string(),
+                ..Default::default()
+            }),
+            current_state: RefCell::new(None),
+            clock: RefCell::new(Rc::new(SystemClock)),
+        }
+    }
+
+    pub fn set_clock(&self, clock: SharedClock) {
+        *self.clock.borrow_mut() = clock;
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.snapshot.borrow().clone()
+    }
+
+    fn increment(counters: &mut BTreeMap<String, u64>, key: String) {
+        *counters.entry(key).or_default() += 1;
+    }
+
+    /// Move the current state to state_name, recording how long we were in the previous one
+    fn state_started(&self, state_name: String, now: Instant) {
+        let previous = self.current_state.replace(Some((state_name, now)));
+        if let Some((previous_state, started_at)) = previous {
+            self.snapshot
+                .borrow_mut()
+                .time_in_state
+                .entry(previous_state)
+                .or_default()
+                .observe(now.saturating_duration_since(started_at));
+        }
+    }
+}
+
+impl<StateT: Display, EventT: StateEventConstraint> HsmObserver<StateT, EventT> for HsmMetrics {
+    fn on_event_dispatched(&self, event: &EventT) {
+        Self::increment(
+            &mut self.snapshot.borrow_mut().dispatches,
+            event.get_event_name(),
+        );
+    }
+
+    fn on_event_handled(&self, state: &StateT, _event: &EventT) {
+        Self::increment(
+            &mut self.snapshot.borrow_mut().handled_by_state,
+            state.to_string(),
+        );
+    }
+
+    fn on_event_unhandled(&self, event: &EventT) {
+        Self::increment(
+            &mut self.snapshot.borrow_mut().unhandled,
+            event.get_event_name(),
+        );
+    }
+
+    fn on_state_started(&self, state: &StateT) {
+        let now = self.clock.borrow().now();
+        self.state_started(state.to_string(), now);
+    }
+
+    fn on_transition_end(&self, source: &StateT, target: &StateT) {
+        *self
+            .snapshot
+            .borrow_mut()
+            .transitions
+            .entry((source.to_string(), target.to_string()))
+            .or_default() += 1;
+    }
+
+    fn on_error(&self, error: &HSMError<StateT>) {
+        Self::increment(
+            &mut self.snapshot.borrow_mut().errors,
+            error.variant_name().to_string(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::{ExampleEvents, ExampleStates};
+
+    type Observer = dyn HsmObserver<ExampleStates, ExampleEvents>;
+
+    #[test]
+    fn histogram_buckets() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_millis(5));
+        histogram.observe(Duration::from_secs(2));
+        assert_eq!(histogram.count, 2);
+        assert_eq!(histogram.bucket_counts[0], 0);
+        assert_eq!(histogram.bucket_counts[1], 1);
+        assert_eq!(histogram.bucket_counts[5], 2);
+        assert!((histogram.sum_seconds - 2.005).abs() < 1e-9);
+    }
+
+    #[test]
+    fn prometheus_rendering() {
+        let metrics = HsmMetrics::new("Test\"Hsm");
+        let observer: &Observer = &metrics;
+        observer.on_state_started(&ExampleStates::LevelA2);
+        observer.on_event_dispatched(&ExampleEvents::A);
+        observer.on_event_handled(&ExampleStates::LevelA1, &ExampleEvents::A);
+        observer.on_event_dispatched(&ExampleEvents::A);
+        observer.on_event_unhandled(&ExampleEvents::A);
+        observer.on_state_started(&ExampleStates::LevelB1);
+        observer.on_transition_end(&ExampleStates::LevelA2, &ExampleStates::LevelB1);
+        observer.on_error(&HSMError::EngineNotInitialized());
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.dispatches.get("A"), Some(&2));
+        assert_eq!(snapshot.time_in_state.get("LevelA2").unwrap().count, 1);
+        assert!(!snapshot.time_in_state.contains_key("LevelB1"));
+
+        let text = snapshot.to_prometheus();
+        let expected_lines = [
+            "# TYPE rust_hsm_dispatches_total counter",
+            "rust_hsm_dispatches_total{hsm=\"Test\\\"Hsm\",event=\"A\"} 2",
+            "rust_hsm_handled_total{hsm=\"Test\\\"Hsm\",state=\"LevelA1\"} 1",
+            "rust_hsm_unhandled_total{hsm=\"Test\\\"Hsm\",event=\"A\"} 1",
+            "rust_hsm_transitions_total{hsm=\"Test\\\"Hsm\",source=\"LevelA2\",target=\"LevelB1\"} 1",
+            "rust_hsm_errors_total{hsm=\"Test\\\"Hsm\",error=\"EngineNotInitialized\"} 1",
+            "# TYPE rust_hsm_time_in_state_seconds histogram",
+            "rust_hsm_time_in_state_seconds_bucket{hsm=\"Test\\\"Hsm\",state=\"LevelA2\",le=\"+Inf\"} 1",
+            "rust_hsm_time_in_state_seconds_count{hsm=\"Test\\\"Hsm\",state=\"LevelA2\"} 1",
+        ];
+        for expected in expected_lines {
+            assert!(
+                text.lines().any(|line| line == expected),
+                "Missing {} in:\n{}",
+                expected,
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Lets consumers listen in on the engine's lifecycle without forking it.
+//! Useful for metrics, auditing, UI's, etc.
+use crate::errors::HSMError;
+use std::rc::Rc;
+
+/// # Brief
+/// Callbacks made by the engine as it handles events.
+/// Every callback has an empty default impl, so only implement what you care about.
+/// # Note
+/// Callbacks are made while the engine is mid-operation.
+/// Do NOT dispatch events into the HSM or register observers from within them.
+/// If you need to mutate your observer's data, use interior mutability (i.e. RefCell).
+pub trait HsmObserver<StateT, EventT> {
+    /// A consumer dispatched an event into the HSM.
+    fn on_event_dispatched(&self, _event: &EventT) {}
+
+    /// The event is about to be offered to the state.
+    fn on_event_offered(&self, _state: &StateT, _event: &EventT) {}
+
+    /// The state handled the event. It will not be offered to any other states.
+    fn on_event_handled(&self, _state: &StateT, _event: &EventT) {}
+
+    /// The event was offered all the way up to the top state and nobody handled it.
+    fn on_event_unhandled(&self, _event: &EventT) {}
+
+    fn on_state_exited(&self, _state: &StateT) {}
+
+    fn on_state_entered(&self, _state: &StateT) {}
+
+    fn on_state_started(&self, _state: &StateT) {}
+
+    /// A state asked the engine to change state from source -> target.
+    fn on_transition_begin(&self, _source: &StateT, _target: &StateT) {}
+
+    /// All exits/enters/starts for the transition source -> target are done.
+    fn on_transition_end(&self, _source: &StateT, _target: &StateT) {}
+
+    /// A state fired an event back at the engine. It will be handled once the current event completes.
+    fn on_event_enqueued(&self, _event: &EventT) {}
+
+    /// The engine raised an error (either to the consumer or to a state).
+    fn on_error(&self, _error: &HSMError<StateT>) {}
+}
+
+/// Observers are shared so consumers can keep a handle to whatever data they collect.
+pub type SharedObserver<StateT, EventT> = Rc<dyn HsmObserver<StateT, EventT>>;
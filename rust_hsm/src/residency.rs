@@ -0,0 +1,119 @@
+//! Tracks how long the machine has spent in each state.
+//! Residency is tracked for the current (leaf) state, i.e. time in DIMMER is not also time in ON.
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// How much time the machine has spent in a state, as its current (leaf) state.
+/// Composite states only accrue time while they are the current state themselves.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateResidency {
+    /// Times the state became the current state
+    pub entry_count: u64,
+    /// Cumulative time as the current state (including the ongoing visit)
+    pub total_time: Duration,
+    /// Last time the state became the current state (None if never)
+    pub last_entered: Option<Instant>,
+}
+
+pub(crate) struct ResidencyTracker<StateT> {
+    /// Only includes completed visits. The ongoing visit is added on request.
+    residencies: HashMap<StateT, StateResidency>,
+    current: Option<(StateT, Instant)>,
+}
+
+impl<StateT> Default for ResidencyTracker<StateT> {
+    fn default() -> Self {
+        Self {
+            residencies: HashMap::new(),
+            current: None,
+        }
+    }
+}
+
+impl<StateT: Copy + Eq + Hash> ResidencyTracker<StateT> {
+    /// The state became the current state at now
+    pub(crate) fn state_entered(&mut self, state_id: StateT, now: Instant) {
+        self.close_current_visit(now);
+        let residency = self.residencies.entry(state_id).or_default();
+        residency.entry_count += 1;
+        residency.last_entered = Some(now);
+        self.current = Some((state_id, now));
+    }
+
+    fn close_current_visit(&mut self, now: Instant) {
+        if let Some((state_id, entered_at)) = self.current.take() {
+            self.residencies.entry(state_id).or_default().total_time +=
+                now.saturating_duration_since(entered_at);
+        }
+    }
+
+    pub(crate) fn time_in_current_state(&self, now: Instant) -> Option<Duration> {
+        self.current
+            .map(|(_, entered_at)| now.saturating_duration_since(entered_at))
+    }
+
+    pub(crate) fn residency(&self, state_id: &StateT, now: Instant) -> StateResidency {
+        let mut residency = self.residencies.get(state_id).cloned().unwrap_or_default();
+        if let Some((current_id, entered_at)) = self.current {
+            if current_id == *state_id {
+                residency.total_time += now.saturating_duration_since(entered_at);
+            }
+        }
+        residency
+    }
+
+    /// Forget everything, i.e. to start a new reporting window. The current visit restarts at now.
+    pub(crate) fn reset(&mut self, now: Instant) {
+        self.residencies.clear();
+        if let Some((current_id, _)) = self.current {
+            self.current = Some((current_id, now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn residency() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let (a, b) = (1, 2);
+
+        let mut tracker = ResidencyTracker::default();
+        assert!(tracker.time_in_current_state(at(0)).is_none());
+
+        tracker.state_entered(a, at(0));
+        tracker.state_entered(b, at(10));
+        tracker.state_entered(a, at(15));
+
+        assert_eq!(
+            tracker.time_in_current_state(at(20)),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            tracker.residency(&a, at(20)),
+            StateResidency {
+                entry_count: 2,
+                total_time: Duration::from_secs(15),
+                last_entered: Some(at(15)),
+            }
+        );
+        assert_eq!(
+            tracker.residency(&b, at(20)).total_time,
+            Duration::from_secs(5)
+        );
+        assert_eq!(tracker.residency(&3, at(20)), Default::default());
+
+        tracker.reset(at(20));
+        assert_eq!(tracker.residency(&b, at(30)), Default::default());
+        assert_eq!(
+            tracker.residency(&a, at(30)).total_time,
+            Duration::from_secs(10)
+        );
+    }
+}
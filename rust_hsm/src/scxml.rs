@@ -0,0 +1,740 @@
+//! Loads W3C SCXML documents (i.e. charts authored in SCXML-capable editors) into a MachineDefinition,
+//! and writes HSMs back out as SCXML (i.e. for statechart viewers and simulators).
+//! SCXML state ids are bound to the states whose Display matches, transition events to event names.
+//! Only the topology is loaded: anything executable (or without an HSM equivalent) is reported
+//! as unsupported. Elements and attributes of other namespaces (i.e. editor metadata) are ignored.
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write},
+};
+
+use roxmltree::{Document, Node};
+
+use crate::{
+    errors::{HSMError, HSMResult},
+    events::StateEventConstraint,
+    machine_definition::MachineDefinition,
+    state::{HistoryMode, StateConstraint},
+    state_mapping::StateMapping,
+};
+
+const SCXML_NAMESPACE: &str = "http://www.w3.org/2005/07/scxml";
+
+/// A single problem found in an SCXML document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScxmlIssue {
+    /// The document is not well-formed XML
+    Malformed(String),
+    /// The root element is not `<scxml>`
+    NotScxml,
+    /// An element or attribute without an HSM equivalent (i.e. `<parallel>` or cond)
+    Unsupported { construct: String, line: u32 },
+    MissingAttribute {
+        element: String,
+        attribute: String,
+        line: u32,
+    },
+    /// The id does not match any of the states
+    UnknownState { id: String, line: u32 },
+    /// The event does not match any of the event names
+    UnknownEvent { event: String, line: u32 },
+    /// The HSM needs exactly one top level `<state>` (its top state)
+    TopStateCount(usize),
+    /// Only when writing: the state has children but no initial child. The HSM stops at it,
+    /// while SCXML would enter its first child.
+    NoInitialChild(String),
+}
+
+impl Display for ScxmlIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScxmlIssue::Malformed(err) => write!(f, "Malformed XML: {}", err),
+            ScxmlIssue::NotScxml => write!(f, "The root element is not <scxml>"),
+            ScxmlIssue::Unsupported { construct, line } => {
+                write!(f, "Line {}: {} is not supported", line, construct)
+            }
+            ScxmlIssue::MissingAttribute {
+                element,
+                attribute,
+                line,
+            } => write!(f, "Line {}: <{}> is missing {}", line, element, attribute),
+            ScxmlIssue::UnknownState { id, line } => {
+                write!(f, "Line {}: No state matches id {}", line, id)
+            }
+            ScxmlIssue::UnknownEvent { event, line } => {
+                write!(f, "Line {}: No event matches {}", line, event)
+            }
+            ScxmlIssue::TopStateCount(count) => {
+                write!(f, "Expected exactly one top level <state>, found {}", count)
+            }
+            ScxmlIssue::NoInitialChild(state) => {
+                write!(f, "State {} has children but no initial child", state)
+            }
+        }
+    }
+}
+
+/// # Brief
+/// Load the topology of an SCXML document.
+/// Supports `<state>` (nested), initial (attribute or `<initial>`), `<history>` and
+/// `<transition>` with events and a single target.
+/// # Arguments
+/// * `document` - The SCXML document
+/// * `states` - Every state ids may refer to, matched by their Display
+/// * `event_names` - Every event name transitions may refer to (see StateEventConstraint::get_event_name)
+/// # Errors
+/// InvalidScxml with every issue found. The topology itself is only validated when building.
+pub fn parse_scxml<StateT: StateConstraint>(
+    document: &str,
+    states: &[StateT],
+    event_names: &[&str],
+) -> HSMResult<MachineDefinition<StateT>, StateT> {
+    let document = Document::parse(document)
+        .map_err(|err| HSMError::InvalidScxml(vec![ScxmlIssue::Malformed(err.to_string())]))?;
+    let root = document.root_element();
+    if !is_scxml_element(&root) || root.tag_name().name() != "scxml" {
+        return Err(HSMError::InvalidScxml(vec![ScxmlIssue::NotScxml]));
+    }
+
+    let mut parser = ScxmlParser {
+        document: &document,
+        states: states
+            .iter()
+            .map(|state| (state.to_string(), *state))
+            .collect(),
+        event_names,
+        history_parents: HashMap::new(),
+        parents: vec![],
+        initial_children: vec![],
+        history: vec![],
+        transitions: vec![],
+        issues: vec![],
+    };
+    parser.collect_history(root, None);
+    parser.parse_children(root, None);
+
+    let top_states: Vec<StateT> = parser
+        .parents
+        .iter()
+        .filter(|(_, parent)| parent.is_none())
+        .map(|(state, _)| *state)
+        .collect();
+    if top_states.len() != 1 {
+        parser
+            .issues
+            .push(ScxmlIssue::TopStateCount(top_states.len()));
+    }
+    // Defaults to the first top level state
+    let initial_state = match root.attribute("initial") {
+        None => top_states.first().copied(),
+        Some(initial) => parser.resolve_target(&root, initial),
+    };
+
+    match (initial_state, parser.issues.is_empty()) {
+        (Some(initial_state), true) => Ok(MachineDefinition {
+            states: parser.parents,
+            initial_children: parser.initial_children,
+            history: parser.history,
+            transitions: parser.transitions,
+            initial_state,
+        }),
+        _ => Err(HSMError::InvalidScxml(parser.issues)),
+    }
+}
+
+/// # Brief
+/// Write the hierarchy, initial states, history and declared transitions as an SCXML document.
+/// Only transitions declared with their events are written (SCXML transitions without
+/// events are taken immediately). States with history get a `<history>` child,
+/// which transitions into the state target and defaults to its initial child.
+/// # Errors
+/// InvalidScxml listing every state with children but no initial child, as SCXML has no way
+/// to stop at them
+pub(crate) fn to_scxml<StateT: StateConstraint, EventT: StateEventConstraint>(
+    state_mapping: &StateMapping<StateT, EventT>,
+) -> HSMResult<String, StateT> {
+    let issues: Vec<ScxmlIssue> = state_mapping
+        .get_all_state_ids()
+        .into_iter()
+        .filter(|state| {
+            !state_mapping.get_children_state_ids(state).is_empty()
+                && state_mapping.get_initial_child(state).is_none()
+        })
+        .map(|state| ScxmlIssue::NoInitialChild(state.to_string()))
+        .collect();
+    if !issues.is_empty() {
+        return Err(HSMError::InvalidScxml(issues));
+    }
+
+    let mut document = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = write!(
+        document,
+        "<scxml xmlns=\"{}\" version=\"1.0\"",
+        SCXML_NAMESPACE
+    );
+    if let Some(initial_state) = state_mapping.get_initial_state() {
+        let _ = write!(document, " initial=\"{}\"", escape(&initial_state));
+    }
+    document.push_str(">\n");
+    for state in state_mapping.get_all_state_ids() {
+        if state_mapping.get_parent_state_id(&state).is_none() {
+            write_state(&mut document, state_mapping, state, 1);
+        }
+    }
+    document.push_str("</scxml>\n");
+    Ok(document)
+}
+
+fn write_state<StateT: StateConstraint, EventT: StateEventConstraint>(
+    document: &mut String,
+    state_mapping: &StateMapping<StateT, EventT>,
+    state: StateT,
+    depth: usize,
+) {
+    let indent = "    ".repeat(depth);
+    let _ = write!(document, "{}<state id=\"{}\"", indent, escape(&state));
+    if let Some(initial_child) = state_mapping.get_initial_child(&state) {
+        let _ = write!(document, " initial=\"{}\"", escape(&initial_child));
+    }
+
+    let transitions = state_mapping.get_transitions();
+    let labelled_targets: Vec<(StateT, &[String])> = transitions
+        .edges()
+        .into_iter()
+        .filter(|(source, _)| *source == state)
+        .map(|(_, target)| (target, transitions.events(state, target)))
+        .filter(|(_, events)| !events.is_empty())
+        .collect();
+    let children = state_mapping.get_children_state_ids(&state);
+    let history_mode = state_mapping.get_history_mode(&state);
+    if labelled_targets.is_empty() && children.is_empty() && history_mode.is_none() {
+        document.push_str("/>\n");
+        return;
+    }
+    document.push_str(">\n");
+
+    if let Some(history_mode) = history_mode {
+        let history_type = match history_mode {
+            HistoryMode::Shallow => "shallow",
+            HistoryMode::Deep => "deep",
+        };
+        let _ = writeln!(
+            document,
+            "{}    <history id=\"{}\" type=\"{}\">",
+            indent,
+            history_id(&state),
+            history_type
+        );
+        // History is only set on states with children, which all have an initial child here
+        if let Some(initial_child) = state_mapping.get_initial_child(&state) {
+            let _ = writeln!(
+                document,
+                "{}        <transition target=\"{}\"/>",
+                indent,
+                escape(&initial_child)
+            );
+        }
+        let _ = writeln!(document, "{}    </history>", indent);
+    }
+    for (target, events) in labelled_targets {
+        let target_id = match state_mapping.get_history_mode(&target) {
+            Some(_) => history_id(&target),
+            None => escape(&target),
+        };
+        let _ = writeln!(
+            document,
+            "{}    <transition event=\"{}\" target=\"{}\"/>",
+            indent,
+            escape(&events.join(" ")),
+            target_id
+        );
+    }
+    for child in children {
+        write_state(document, state_mapping, child, depth + 1);
+    }
+    let _ = writeln!(document, "{}</state>", indent);
+}
+
+fn history_id(state: &impl Display) -> String {
+    escape(&format!("{}_history", state))
+}
+
+/// Escape the value of an XML attribute
+fn escape(value: &impl Display) -> String {
+    value
+        .to_string()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn is_scxml_element(node: &Node) -> bool {
+    node.is_element() && matches!(node.tag_name().namespace(), None | Some(SCXML_NAMESPACE))
+}
+
+struct ScxmlParser<'a, 'input, StateT: StateConstraint> {
+    document: &'a Document<'input>,
+    /// Display -> state
+    states: HashMap<String, StateT>,
+    event_names: &'a [&'a str],
+    /// `<history>` id -> state it belongs to (only states with known ids)
+    history_parents: HashMap<String, StateT>,
+    parents: Vec<(StateT, Option<StateT>)>,
+    initial_children: Vec<(StateT, StateT)>,
+    history: Vec<(StateT, HistoryMode)>,
+    transitions: Vec<(StateT, String, StateT)>,
+    issues: Vec<ScxmlIssue>,
+}
+
+impl<StateT: StateConstraint> ScxmlParser<'_, '_, StateT> {
+    fn line(&self, node: &Node) -> u32 {
+        self.document.text_pos_at(node.range().start).row
+    }
+
+    fn unsupported(&mut self, node: &Node, construct: String) {
+        let line = self.line(node);
+        self.issues
+            .push(ScxmlIssue::Unsupported { construct, line });
+    }
+
+    /// Required attribute of an SCXML element
+    fn attribute<'n>(&mut self, node: &Node<'n, '_>, attribute: &str) -> Option<&'n str> {
+        let value = node.attribute(attribute);
+        if value.is_none() {
+            let line = self.line(node);
+            self.issues.push(ScxmlIssue::MissingAttribute {
+                element: node.tag_name().name().to_string(),
+                attribute: attribute.to_string(),
+                line,
+            });
+        }
+        value
+    }
+
+    fn resolve_state(&mut self, node: &Node, id: &str) -> Option<StateT> {
+        let state = self.states.get(id).copied();
+        if state.is_none() {
+            let line = self.line(node);
+            self.issues.push(ScxmlIssue::UnknownState {
+                id: id.to_string(),
+                line,
+            });
+        }
+        state
+    }
+
+    /// A single state (or history) id, as targeted by a transition or an initial
+    fn resolve_target(&mut self, node: &Node, target: &str) -> Option<StateT> {
+        let ids: Vec<&str> = target.split_whitespace().collect();
+        match ids.as_slice() {
+            [id] => match self.history_parents.get(*id) {
+                // Entering a state with history is what resumes it
+                Some(parent) => Some(*parent),
+                None => self.resolve_state(node, id),
+            },
+            _ => {
+                self.unsupported(node, format!("Targeting {} states at once", ids.len()));
+                None
+            }
+        }
+    }
+
+    /// History is targeted by id, so it has to be known before any transition is parsed
+    fn collect_history(&mut self, node: Node, parent: Option<StateT>) {
+        for child in node.children().filter(is_scxml_element) {
+            match child.tag_name().name() {
+                "state" => {
+                    let state = child
+                        .attribute("id")
+                        .and_then(|id| self.states.get(id).copied());
+                    self.collect_history(child, state);
+                }
+                "history" => {
+                    if let (Some(id), Some(parent)) = (child.attribute("id"), parent) {
+                        self.history_parents.insert(id.to_string(), parent);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Parse the children of `<scxml>` or of a `<state>`
+    fn parse_children(&mut self, node: Node, parent: Option<StateT>) {
+        for child in node.children().filter(is_scxml_element) {
+            match (child.tag_name().name(), parent) {
+                ("state", _) => self.parse_state(child, parent),
+                ("transition", Some(source)) => self.parse_transition(child, source),
+                ("history", Some(state)) => self.parse_history(child, state),
+                // Handled by parse_state
+                ("initial", Some(_)) => {}
+                (name, _) => self.unsupported(&child, format!("<{}>", name)),
+            }
+        }
+    }
+
+    fn parse_state(&mut self, node: Node, parent: Option<StateT>) {
+        let Some(id) = self.attribute(&node, "id") else {
+            return;
+        };
+        let Some(state) = self.resolve_state(&node, id) else {
+            return;
+        };
+        self.parents.push((state, parent));
+
+        let initial_elements: Vec<Node> = node
+            .children()
+            .filter(|child| is_scxml_element(child) && child.tag_name().name() == "initial")
+            .collect();
+        let initial_target = match (node.attribute("initial"), initial_elements.as_slice()) {
+            (Some(initial), []) => Some(initial),
+            (None, [initial_element]) => self.initial_element_target(initial_element),
+            (None, []) => None,
+            _ => {
+                self.unsupported(&node, "More than one initial".to_string());
+                None
+            }
+        };
+        // Defaults to the first child state
+        let initial_child = match initial_target {
+            Some(target) => self.resolve_target(&node, target),
+            None => node
+                .children()
+                .find(|child| is_scxml_element(child) && child.tag_name().name() == "state")
+                .and_then(|child| child.attribute("id"))
+                .and_then(|id| self.states.get(id).copied()),
+        };
+        if let Some(initial_child) = initial_child {
+            self.initial_children.push((state, initial_child));
+        }
+
+        self.parse_children(node, Some(state));
+    }
+
+    fn initial_element_target<'n>(&mut self, node: &Node<'n, '_>) -> Option<&'n str> {
+        let transitions: Vec<Node> = node.children().filter(is_scxml_element).collect();
+        match transitions.as_slice() {
+            [transition] if transition.tag_name().name() == "transition" => {
+                self.attribute(transition, "target")
+            }
+            _ => {
+                self.unsupported(node, "<initial> without a single <transition>".to_string());
+                None
+            }
+        }
+    }
+
+    fn parse_history(&mut self, node: Node, state: StateT) {
+        self.attribute(&node, "id");
+        let history_mode = match node.attribute("type") {
+            None | Some("shallow") => HistoryMode::Shallow,
+            Some("deep") => HistoryMode::Deep,
+            Some(history_type) => {
+                self.unsupported(&node, format!("History type {}", history_type));
+                return;
+            }
+        };
+        // The initial child is resumed until the state was exited once,
+        // so the default transition may only lead there
+        let transitions: Vec<Node> = node.children().filter(is_scxml_element).collect();
+        match transitions.as_slice() {
+            [] => {}
+            [transition] if transition.tag_name().name() == "transition" => {
+                let initial_child = self
+                    .initial_children
+                    .iter()
+                    .find(|(parent, _)| *parent == state)
+                    .map(|(_, child)| *child);
+                let target = self
+                    .attribute(transition, "target")
+                    .and_then(|target| self.resolve_target(transition, target));
+                if target.is_some() && target != initial_child {
+                    self.unsupported(
+                        transition,
+                        "Default <transition> of <history> to other than the initial child"
+                            .to_string(),
+                    );
+                }
+            }
+            _ => {
+                self.unsupported(&node, "<history> without a single <transition>".to_string());
+            }
+        }
+        self.history.push((state, history_mode));
+    }
+
+    fn parse_transition(&mut self, node: Node, source: StateT) {
+        if node.attribute("cond").is_some() {
+            self.unsupported(&node, "cond on <transition>".to_string());
+        }
+        if node.attribute("type") == Some("internal") {
+            self.unsupported(&node, "Internal <transition>".to_string());
+        }
+        if node.children().any(|child| is_scxml_element(&child)) {
+            self.unsupported(&node, "Executable content in <transition>".to_string());
+        }
+        let Some(events) = node.attribute("event") else {
+            self.unsupported(&node, "<transition> without event".to_string());
+            return;
+        };
+        let target = self
+            .attribute(&node, "target")
+            .and_then(|target| self.resolve_target(&node, target));
+        // Events are checked even when the target is not, so every issue is reported
+        for event in events.split_whitespace() {
+            if !self.event_names.contains(&event) {
+                let line = self.line(&node);
+                self.issues.push(ScxmlIssue::UnknownEvent {
+                    event: event.to_string(),
+                    line,
+                });
+                continue;
+            }
+            if let Some(target) = target {
+                self.transitions.push((source, event.to_string(), target));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::HsmBuilder,
+        examples::{A1Impl, B1Impl, ExampleEvents, ExampleFData, ExampleStates, Top},
+        test_utils::{create_test_builder, ChangingA2},
+    };
+    use log::LevelFilter;
+
+    const STATES: [ExampleStates; 4] = [
+        ExampleStates::Top,
+        ExampleStates::LevelA1,
+        ExampleStates::LevelB1,
+        ExampleStates::LevelA2,
+    ];
+    const EVENT_NAMES: [&str; 6] = ["A", "B", "C", "D", "E", "F"];
+
+    #[test]
+    fn parse() {
+        let document = r#"<?xml version="1.0" encoding="UTF-8"?>
+<scxml xmlns="http://www.w3.org/2005/07/scxml" xmlns:qt="http://www.qt.io/2015/02/scxml-ext"
+       version="1.0" initial="LevelA2">
+    <qt:editorinfo initialGeometry="0;0;0;0"/>
+    <state id="Top">
+        <state id="LevelA1">
+            <history id="LevelA1History" type="deep">
+                <transition target="LevelA2"/>
+            </history>
+            <state id="LevelA2">
+                <transition event="F" target="LevelB1"/>
+            </state>
+        </state>
+        <state id="LevelB1">
+            <transition event="A C" target="LevelA1History"/>
+        </state>
+    </state>
+</scxml>"#;
+        let definition = parse_scxml(document, &STATES, &EVENT_NAMES).unwrap();
+        assert_eq!(
+            definition,
+            MachineDefinition {
+                states: vec![
+                    (ExampleStates::Top, None),
+                    (ExampleStates::LevelA1, Some(ExampleStates::Top)),
+                    (ExampleStates::LevelA2, Some(ExampleStates::LevelA1)),
+                    (ExampleStates::LevelB1, Some(ExampleStates::Top)),
+                ],
+                initial_children: vec![
+                    (ExampleStates::Top, ExampleStates::LevelA1),
+                    (ExampleStates::LevelA1, ExampleStates::LevelA2),
+                ],
+                history: vec![(ExampleStates::LevelA1, HistoryMode::Deep)],
+                transitions: vec![
+                    (
+                        ExampleStates::LevelA2,
+                        "F".to_string(),
+                        ExampleStates::LevelB1
+                    ),
+                    (
+                        ExampleStates::LevelB1,
+                        "A".to_string(),
+                        ExampleStates::LevelA1
+                    ),
+                    (
+                        ExampleStates::LevelB1,
+                        "C".to_string(),
+                        ExampleStates::LevelA1
+                    ),
+                ],
+                initial_state: ExampleStates::LevelA2,
+            }
+        );
+
+        let mut builder = HsmBuilder::<ExampleStates, ExampleEvents>::new(
+            "TestHsm".to_string(),
+            LevelFilter::Info,
+        );
+        let delegate = builder.get_delegate();
+        builder.add_machine_definition(&definition, |state| match state {
+            ExampleStates::Top => Top::new(delegate.clone()),
+            ExampleStates::LevelA1 => A1Impl::new(delegate.clone()),
+            ExampleStates::LevelB1 => B1Impl::new(delegate.clone()),
+            ExampleStates::LevelA2 => ChangingA2::new(delegate.clone()),
+        });
+        let hsm = builder.build(definition.initial_state).unwrap();
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelB1);
+    }
+
+    #[test]
+    fn to_scxml() {
+        let mut builder = create_test_builder();
+        builder
+            .set_initial_child(ExampleStates::Top, ExampleStates::LevelA1)
+            .set_initial_child(ExampleStates::LevelA1, ExampleStates::LevelA2)
+            .set_history(ExampleStates::LevelA1, HistoryMode::Shallow)
+            .add_transition_event(ExampleStates::LevelA2, "F", ExampleStates::LevelB1)
+            .add_transition_event(ExampleStates::LevelA2, "C", ExampleStates::LevelB1)
+            .add_transition_event(ExampleStates::LevelB1, "A", ExampleStates::LevelA1);
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+        let document = hsm.to_scxml().unwrap();
+        assert_eq!(
+            document,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" initial="LevelA2">
+    <state id="Top" initial="LevelA1">
+        <state id="LevelA1" initial="LevelA2">
+            <history id="LevelA1_history" type="shallow">
+                <transition target="LevelA2"/>
+            </history>
+            <state id="LevelA2">
+                <transition event="F C" target="LevelB1"/>
+            </state>
+        </state>
+        <state id="LevelB1">
+            <transition event="A" target="LevelA1_history"/>
+        </state>
+    </state>
+</scxml>
+"#
+        );
+
+        // Loads back into the same topology
+        let definition = parse_scxml(&document, &STATES, &EVENT_NAMES).unwrap();
+        assert_eq!(
+            definition.states,
+            vec![
+                (ExampleStates::Top, None),
+                (ExampleStates::LevelA1, Some(ExampleStates::Top)),
+                (ExampleStates::LevelA2, Some(ExampleStates::LevelA1)),
+                (ExampleStates::LevelB1, Some(ExampleStates::Top)),
+            ]
+        );
+        assert_eq!(
+            definition.initial_children,
+            vec![
+                (ExampleStates::Top, ExampleStates::LevelA1),
+                (ExampleStates::LevelA1, ExampleStates::LevelA2),
+            ]
+        );
+        assert_eq!(
+            definition.history,
+            vec![(ExampleStates::LevelA1, HistoryMode::Shallow)]
+        );
+        assert_eq!(definition.transitions.len(), 3);
+        assert_eq!(definition.initial_state, ExampleStates::LevelA2);
+    }
+
+    #[test]
+    fn to_scxml_needs_initial_children() {
+        // Would load back entering LevelA2, where the HSM stops at LevelA1
+        let hsm = create_test_builder().build(ExampleStates::LevelA1).unwrap();
+        assert!(matches!(
+            hsm.to_scxml(),
+            Err(HSMError::InvalidScxml(issues))
+                if issues == vec![
+                    ScxmlIssue::NoInitialChild("Top".to_string()),
+                    ScxmlIssue::NoInitialChild("LevelA1".to_string()),
+                ]
+        ));
+
+        let mut builder = create_test_builder();
+        builder.set_initial_child(ExampleStates::Top, ExampleStates::LevelA1);
+        let hsm = builder.build(ExampleStates::LevelA1).unwrap();
+        assert!(matches!(
+            hsm.to_scxml(),
+            Err(HSMError::InvalidScxml(issues))
+                if issues == vec![ScxmlIssue::NoInitialChild("LevelA1".to_string())]
+        ));
+    }
+
+    #[test]
+    fn reports_every_issue() {
+        let document = r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0">
+    <state id="Top">
+        <history id="TopHistory"><transition target="LevelB1"/></history>
+        <parallel id="Regions"/>
+        <state id="LevelA1">
+            <transition event="F" cond="x > 1" target="Top"/>
+            <transition event="Z" target="Unknown"/>
+            <transition event="A" target="Top LevelA1"/>
+        </state>
+        <state/>
+    </state>
+    <state id="LevelB1"/>
+</scxml>"#;
+        let issues = match parse_scxml(document, &STATES, &EVENT_NAMES) {
+            Err(HSMError::InvalidScxml(issues)) => issues,
+            result => panic!("Expected InvalidScxml, got {:?}", result),
+        };
+        assert_eq!(
+            issues,
+            vec![
+                ScxmlIssue::Unsupported {
+                    construct: "Default <transition> of <history> to other than the initial child"
+                        .to_string(),
+                    line: 3
+                },
+                ScxmlIssue::Unsupported {
+                    construct: "<parallel>".to_string(),
+                    line: 4
+                },
+                ScxmlIssue::Unsupported {
+                    construct: "cond on <transition>".to_string(),
+                    line: 6
+                },
+                ScxmlIssue::UnknownState {
+                    id: "Unknown".to_string(),
+                    line: 7
+                },
+                ScxmlIssue::UnknownEvent {
+                    event: "Z".to_string(),
+                    line: 7
+                },
+                ScxmlIssue::Unsupported {
+                    construct: "Targeting 2 states at once".to_string(),
+                    line: 8
+                },
+                ScxmlIssue::MissingAttribute {
+                    element: "state".to_string(),
+                    attribute: "id".to_string(),
+                    line: 10
+                },
+                ScxmlIssue::TopStateCount(2),
+            ]
+        );
+        assert_eq!(issues[1].to_string(), "Line 4: <parallel> is not supported");
+
+        assert!(matches!(
+            parse_scxml(r#"<scxml><state id="Top">"#, &STATES, &EVENT_NAMES),
+            Err(HSMError::InvalidScxml(issues)) if matches!(issues[..], [ScxmlIssue::Malformed(_)])
+        ));
+        assert!(matches!(
+            parse_scxml(r#"<chart/>"#, &STATES, &EVENT_NAMES),
+            Err(HSMError::InvalidScxml(issues)) if issues == vec![ScxmlIssue::NotScxml]
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+//! PlantUML and Mermaid sequence diagrams of a DispatchReport, i.e. what the machine did for
+//! a specific event. One lifeline for the engine, and one per state involved.
+//! State names (their Display) are used as is, so they need to be valid identifiers.
+use std::fmt::{Display, Write};
+
+use crate::{
+    dispatch_report::{DispatchReport, TransitionAction},
+    state_diagram_export::DiagramDialect,
+};
+
+/// Lifeline of the engine, which offers the events and performs the transitions
+const ENGINE_LIFELINE: &str = "HSM";
+
+/// # Brief
+/// Render the offers, exits / enters / starts and internal events of the report.
+pub(crate) fn to_sequence_diagram<StateT: Display + PartialEq>(
+    report: &DispatchReport<StateT>,
+    dialect: DiagramDialect,
+) -> String {
+    let mut diagram = String::from(match dialect {
+        DiagramDialect::PlantUml => "@startuml\n",
+        DiagramDialect::Mermaid => "sequenceDiagram\n",
+    });
+    let _ = writeln!(diagram, "    participant {}", ENGINE_LIFELINE);
+    let mut lifelines: Vec<&StateT> = vec![];
+    collect_lifelines(report, &mut lifelines);
+    lifelines.push(&report.final_state);
+    for (index, state) in lifelines.iter().enumerate() {
+        if !lifelines[..index].contains(state) {
+            let _ = writeln!(diagram, "    participant {}", state);
+        }
+    }
+
+    write_report(&mut diagram, report, dialect);
+    for internal_event in &report.internal_events {
+        let _ = match dialect {
+            DiagramDialect::PlantUml => writeln!(
+                diagram,
+                "    == Internal event {} ==",
+                internal_event.event_name
+            ),
+            DiagramDialect::Mermaid => writeln!(
+                diagram,
+                "    Note over {}: Internal event {}",
+                ENGINE_LIFELINE, internal_event.event_name
+            ),
+        };
+        write_report(&mut diagram, internal_event, dialect);
+    }
+
+    let _ = match dialect {
+        DiagramDialect::PlantUml => {
+            writeln!(
+                diagram,
+                "    note over {} : Final state",
+                report.final_state
+            )
+        }
+        DiagramDialect::Mermaid => {
+            writeln!(diagram, "    Note over {}: Final state", report.final_state)
+        }
+    };
+    if dialect == DiagramDialect::PlantUml {
+        diagram.push_str("@enduml\n");
+    }
+    diagram
+}
+
+/// Every state involved (with repeats), in the order they appear in the diagram
+fn collect_lifelines<'a, StateT>(
+    report: &'a DispatchReport<StateT>,
+    lifelines: &mut Vec<&'a StateT>,
+) {
+    lifelines.push(&report.start_state);
+    lifelines.extend(report.offers.iter().map(|offer| &offer.state));
+    lifelines.extend(report.steps.iter().map(|step| &step.state));
+    for internal_event in &report.internal_events {
+        collect_lifelines(internal_event, lifelines);
+    }
+}
+
+/// Offers and steps of the report itself (not its internal events).
+/// Changes of state happen within the handler, so the steps come before its reply.
+fn write_report<StateT: Display>(
+    diagram: &mut String,
+    report: &DispatchReport<StateT>,
+    dialect: DiagramDialect,
+) {
+    let (call_arrow, reply_arrow, separator) = match dialect {
+        DiagramDialect::PlantUml => (" -> ", " --> ", " : "),
+        DiagramDialect::Mermaid => ("->>", "-->>", ": "),
+    };
+    let write_steps = |diagram: &mut String| {
+        for step in &report.steps {
+            let action = match step.action {
+                TransitionAction::Exit => "exit",
+                TransitionAction::Enter => "enter",
+                TransitionAction::Start => "start",
+            };
+            let _ = writeln!(
+                diagram,
+                "    {}{}{}{}{}",
+                ENGINE_LIFELINE, call_arrow, step.state, separator, action
+            );
+        }
+    };
+    for offer in &report.offers {
+        let _ = writeln!(
+            diagram,
+            "    {}{}{}{}{}",
+            ENGINE_LIFELINE, call_arrow, offer.state, separator, report.event_name
+        );
+        let answer = match offer.handled {
+            true => {
+                write_steps(diagram);
+                "handled"
+            }
+            false => "not handled",
+        };
+        let _ = writeln!(
+            diagram,
+            "    {}{}{}{}{}",
+            offer.state, reply_arrow, ENGINE_LIFELINE, separator, answer
+        );
+    }
+    if !report.offers.iter().any(|offer| offer.handled) {
+        write_steps(diagram);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        examples::{ExampleEvents, ExampleFData},
+        test_utils::create_test_hsm,
+    };
+
+    #[test]
+    fn to_plantuml() {
+        let hsm = create_test_hsm();
+        let report = hsm
+            .dispatch_event_with_report(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        assert_eq!(
+            report.to_plantuml(),
+            r#"@startuml
+    participant HSM
+    participant LevelA2
+    participant LevelA1
+    participant LevelB1
+    HSM -> LevelA2 : F
+    HSM -> LevelA2 : exit
+    HSM -> LevelA1 : exit
+    HSM -> LevelB1 : enter
+    HSM -> LevelB1 : start
+    LevelA2 --> HSM : handled
+    note over LevelB1 : Final state
+@enduml
+"#
+        );
+    }
+
+    #[test]
+    fn to_mermaid() {
+        let hsm = create_test_hsm();
+        let report = hsm
+            .dispatch_event_with_report(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        assert_eq!(
+            report.to_mermaid(),
+            r#"sequenceDiagram
+    participant HSM
+    participant LevelA2
+    participant LevelA1
+    participant LevelB1
+    HSM->>LevelA2: F
+    HSM->>LevelA2: exit
+    HSM->>LevelA1: exit
+    HSM->>LevelB1: enter
+    HSM->>LevelB1: start
+    LevelA2-->>HSM: handled
+    Note over LevelB1: Final state
+"#
+        );
+        // B1 fires A, which bubbles up to Top
+        let report = hsm.dispatch_event_with_report(ExampleEvents::E(0)).unwrap();
+        assert_eq!(
+            report.to_mermaid(),
+            r#"sequenceDiagram
+    participant HSM
+    participant LevelB1
+    participant Top
+    HSM->>LevelB1: E
+    LevelB1-->>HSM: handled
+    Note over HSM: Internal event A
+    HSM->>LevelB1: A
+    LevelB1-->>HSM: not handled
+    HSM->>Top: A
+    Top-->>HSM: handled
+    Note over LevelB1: Final state
+"#
+        );
+    }
+}
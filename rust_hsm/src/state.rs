@@ -1,43 +1,60 @@
 //! This file contains the logic for an individual state and how they link together
-use std::{boxed::Box, fmt::Display, vec::Vec};
+use std::{boxed::Box, fmt::Display, hash::Hash, vec::Vec};
 
 use crate::{
     errors::HSMResult, events::StateEventConstraint, state_engine_delegate::SharedDelegate,
 };
 
 /// All valid definitions of a 'class' of state's must be StateTypes.
-/// By enforcing these characteristics, the Engine can translate from its
-/// limited knowledge set to the true state typing provided by the consumer.
-pub trait StateConstraint: Display + Into<u16> + From<u16> + Clone {}
-
-/// An inexpensive token representing a state that can be exchanged for more
-/// complex data structures.
-#[derive(Eq, Hash, PartialEq, Clone, Debug, Copy)]
-pub(crate) struct StateId {
-    id: u16,
+/// The engine uses the states themselves as inexpensive tokens (keys) for its
+/// more complex data structures, so any fieldless enum deriving these qualifies.
+pub trait StateConstraint: Copy + Eq + Hash + Display {}
+
+/// How a composite state with history is re-entered when it is the target of a change.
+/// Without history, it is entered through its initial child (if any).
+/// Unlike UML and SCXML, there is no separate history pseudo-state to target: every change
+/// to the state itself resumes it. Changes to one of its descendants enter that descendant as usual.
+/// Diagrams and SCXML documents draw changes to the state as going to its history pseudo-state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum HistoryMode {
+    /// Resume the child that was active when the state was last exited
+    Shallow,
+    /// Resume the whole nested configuration that was active when the state was last exited
+    Deep,
 }
 
-/// First valid state begins at 0!
-/// State Id can be used as vector index's too
-impl StateId {
-    pub fn new(state_id: u16) -> Self {
-        StateId { id: state_id }
+/// Topology declared next to the state enum itself. Generated by `#[hsm_states]`.
+/// Consumed by HsmBuilder::add_declared_state.
+pub trait StateHierarchy: StateConstraint + 'static {
+    /// Every state with its parent, in the order declared
+    const PARENTS: &'static [(Self, Option<Self>)];
+    /// Every state with its distance from the top state
+    const DEPTHS: &'static [(Self, usize)];
+    /// (parent, initial child) pairs
+    const INITIAL_CHILDREN: &'static [(Self, Self)];
+
+    fn declared_parent(self) -> Option<Self> {
+        Self::PARENTS
+            .iter()
+            .find(|(state, _)| *state == self)
+            .and_then(|(_, parent)| *parent)
     }
 
-    pub fn get_id(&self) -> &u16 {
-        &self.id
+    fn declared_depth(self) -> usize {
+        Self::DEPTHS
+            .iter()
+            .find(|(state, _)| *state == self)
+            .map(|(_, depth)| *depth)
+            .unwrap_or_default()
     }
-}
-
-impl std::fmt::Display for StateId {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "id: {}", self.id)
-    }
-}
 
-impl From<u16> for StateId {
-    fn from(state_id: u16) -> Self {
-        Self::new(state_id)
+    fn declared_initial_child(self) -> Option<Self> {
+        Self::INITIAL_CHILDREN
+            .iter()
+            .find(|(parent, _)| *parent == self)
+            .map(|(_, child)| *child)
     }
 }
 
@@ -71,7 +88,7 @@ pub trait StateIF<StateT, EventT: StateEventConstraint> {
     /// Consequently, make sure any borrows done during handle_event are released before calling this function!
     fn change_state_during_handle(
         &self,
-        new_state: u16,
+        new_state: StateT,
         delegate: SharedDelegate<StateT, EventT>,
     ) -> HSMResult<(), StateT> {
         delegate.change_state(new_state)
@@ -84,11 +101,11 @@ pub type States<StateT, EventT> = Vec<StateBox<StateT, EventT>>;
 /// All elements are cheap data structure or those with copy/clone/rc semantics
 pub(crate) struct StateContainer<StateT: StateConstraint, EventT: StateEventConstraint> {
     pub state_ref: StateBox<StateT, EventT>,
-    pub state_id: StateId,
+    pub state_id: StateT,
 }
 
 impl<StateT: StateConstraint, EventT: StateEventConstraint> StateContainer<StateT, EventT> {
-    pub(crate) fn new(state_id: StateId, state_ref: StateBox<StateT, EventT>) -> Self {
+    pub(crate) fn new(state_id: StateT, state_ref: StateBox<StateT, EventT>) -> Self {
         Self {
             state_ref,
             state_id,
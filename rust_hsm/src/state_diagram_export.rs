@@ -0,0 +1,184 @@
+//! PlantUML and Mermaid state diagrams of an HSM, for Markdown and wiki docs.
+//! Both nest composite states (`state On { ... }`), mark initial states with `[*]`
+//! and draw the declared transitions (see TransitionRegistry).
+//! Transitions into a state with history go to its history pseudo-state
+//! (every change to such a state resumes it, see HistoryMode).
+//! State names (their Display) are used as is, so they need to be valid identifiers.
+use std::fmt::Write;
+
+use crate::{
+    events::StateEventConstraint,
+    state::{HistoryMode, StateConstraint},
+    state_mapping::StateMapping,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DiagramDialect {
+    PlantUml,
+    Mermaid,
+}
+
+/// # Brief
+/// Render the states, initial states and declared transitions of the mapping.
+pub(crate) fn to_state_diagram<StateT: StateConstraint, EventT: StateEventConstraint>(
+    state_mapping: &StateMapping<StateT, EventT>,
+    dialect: DiagramDialect,
+) -> String {
+    let mut diagram = String::from(match dialect {
+        DiagramDialect::PlantUml => "@startuml\n",
+        DiagramDialect::Mermaid => "stateDiagram-v2\n",
+    });
+    if let Some(initial_state) = state_mapping.get_initial_state() {
+        let _ = writeln!(diagram, "    [*] --> {}", initial_state);
+    }
+    for state in state_mapping.get_all_state_ids() {
+        if state_mapping.get_parent_state_id(&state).is_none() {
+            write_state(&mut diagram, state_mapping, dialect, state, 1);
+        }
+    }
+
+    let transitions = state_mapping.get_transitions();
+    for (source, target) in transitions.edges() {
+        let _ = write!(
+            diagram,
+            "    {} --> {}",
+            source,
+            transition_target(state_mapping, dialect, target)
+        );
+        let events = transitions.events(source, target);
+        if !events.is_empty() {
+            let _ = write!(diagram, " : {}", events.join(", "));
+        }
+        diagram.push('\n');
+    }
+    if dialect == DiagramDialect::PlantUml {
+        diagram.push_str("@enduml\n");
+    }
+    diagram
+}
+
+fn write_state<StateT: StateConstraint, EventT: StateEventConstraint>(
+    diagram: &mut String,
+    state_mapping: &StateMapping<StateT, EventT>,
+    dialect: DiagramDialect,
+    state: StateT,
+    depth: usize,
+) {
+    let indent = "    ".repeat(depth);
+    let children = state_mapping.get_children_state_ids(&state);
+    if children.is_empty() {
+        let _ = match dialect {
+            DiagramDialect::PlantUml => writeln!(diagram, "{}state {}", indent, state),
+            DiagramDialect::Mermaid => writeln!(diagram, "{}{}", indent, state),
+        };
+        return;
+    }
+
+    let _ = writeln!(diagram, "{}state {} {{", indent, state);
+    if let Some(initial_child) = state_mapping.get_initial_child(&state) {
+        let _ = writeln!(diagram, "{}    [*] --> {}", indent, initial_child);
+    }
+    // PlantUML has its own history pseudo-states ([H] and [H*]), Mermaid needs one declared
+    if let (DiagramDialect::Mermaid, Some(history_mode)) =
+        (dialect, state_mapping.get_history_mode(&state))
+    {
+        let _ = writeln!(
+            diagram,
+            "{}    state \"{}\" as {}_history",
+            indent,
+            history_label(history_mode),
+            state
+        );
+    }
+    for child in children {
+        write_state(diagram, state_mapping, dialect, child, depth + 1);
+    }
+    let _ = writeln!(diagram, "{}}}", indent);
+}
+
+fn transition_target<StateT: StateConstraint, EventT: StateEventConstraint>(
+    state_mapping: &StateMapping<StateT, EventT>,
+    dialect: DiagramDialect,
+    target: StateT,
+) -> String {
+    match (dialect, state_mapping.get_history_mode(&target)) {
+        (_, None) => target.to_string(),
+        (DiagramDialect::PlantUml, Some(history_mode)) => {
+            format!("{}[{}]", target, history_label(history_mode))
+        }
+        (DiagramDialect::Mermaid, Some(_)) => format!("{}_history", target),
+    }
+}
+
+fn history_label(history_mode: HistoryMode) -> &'static str {
+    match history_mode {
+        HistoryMode::Shallow => "H",
+        HistoryMode::Deep => "H*",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::HsmBuilder,
+        examples::{ExampleEvents, ExampleStates},
+        state::HistoryMode,
+        test_utils::create_test_builder,
+    };
+
+    fn create_test_diagram_builder() -> HsmBuilder<ExampleStates, ExampleEvents> {
+        let mut builder = create_test_builder();
+        builder
+            .set_initial_child(ExampleStates::Top, ExampleStates::LevelA1)
+            .set_history(ExampleStates::LevelA1, HistoryMode::Deep)
+            .add_transition_event(ExampleStates::LevelA2, "F", ExampleStates::LevelB1)
+            .add_transition_event(ExampleStates::LevelB1, "A", ExampleStates::LevelA1);
+        builder
+    }
+
+    #[test]
+    fn to_plantuml() {
+        let hsm = create_test_diagram_builder()
+            .build(ExampleStates::Top)
+            .unwrap();
+        assert_eq!(
+            hsm.to_plantuml().unwrap(),
+            r#"@startuml
+    [*] --> Top
+    state Top {
+        [*] --> LevelA1
+        state LevelA1 {
+            state LevelA2
+        }
+        state LevelB1
+    }
+    LevelA2 --> LevelB1 : F
+    LevelB1 --> LevelA1[H*] : A
+@enduml
+"#
+        );
+    }
+
+    #[test]
+    fn to_mermaid() {
+        let hsm = create_test_diagram_builder()
+            .build(ExampleStates::Top)
+            .unwrap();
+        assert_eq!(
+            hsm.to_mermaid().unwrap(),
+            r#"stateDiagram-v2
+    [*] --> Top
+    state Top {
+        [*] --> LevelA1
+        state LevelA1 {
+            state "H*" as LevelA1_history
+            LevelA2
+        }
+        LevelB1
+    }
+    LevelA2 --> LevelB1 : F
+    LevelB1 --> LevelA1_history : A
+"#
+        );
+    }
+}
@@ -1,33 +1,46 @@
 //! This file contains the logic for a state engine comprised of many
 //! composable states
+#[cfg(feature = "metrics")]
+use crate::metrics::{HsmMetrics, MetricsSnapshot};
+#[cfg(feature = "scxml")]
+use crate::scxml::to_scxml;
 use crate::{
+    chrome_trace::{ChromeTrace, ChromeTraceRecorder, SpanStart},
+    clock::{SharedClock, SystemClock},
+    dispatch_report::{DispatchReport, StateOffer, TransitionAction, TransitionStep},
+    dot_export::to_dot,
     errors::{HSMError, HSMResult},
     events::StateEventConstraint,
+    flight_recorder::{FlightRecorder, HistoryEntry, RecordedTransition, DEFAULT_HISTORY_CAPACITY},
     logger::HSMLogger,
-    state::{StateBox, StateConstraint, StateId},
+    observer::{HsmObserver, SharedObserver},
+    residency::{ResidencyTracker, StateResidency},
+    state::StateConstraint,
+    state_diagram_export::{to_state_diagram, DiagramDialect},
     state_engine_delegate::{EngineDelegateIF, SharedDelegate, WeakDelegate},
     state_mapping::StateMapping,
-    utils::{self, get_function_name, resolve_state_name},
+    trace_format::TraceRecord,
+    transition_registry::TransitionRegistry,
+    utils::get_function_name,
 };
-use core::fmt::Display;
-use log::LevelFilter;
 
 use std::{
-    cell::{Cell, RefCell},
+    cell::{Cell, OnceCell, RefCell},
     default::Default,
     marker::PhantomData,
     rc::{Rc, Weak},
+    time::{Duration, SystemTime},
 };
 
 /// Runs the orchestration of the state 'machine' while considering its hierarchy/
-/// TODO - remove RefCell for StateMapping using a builder.
 // High Level: Engine owns states, states own Rc/shared reference to engine's delegate
 pub(crate) struct HSMEngine<StateT: StateConstraint, EventT: StateEventConstraint> {
     hsm_name: String,
-    current_state: Cell<Option<StateId>>,
+    current_state: Cell<Option<StateT>>,
     /// Used to cache the current known sequence of events and or how we handled the current event.
-    current_handle_string: RefCell<String>,
-    state_mapping: RefCell<StateMapping<StateT, EventT>>,
+    current_trace: RefCell<TraceRecord>,
+    /// Set once by the builder (after validation). Immutable from then on.
+    state_mapping: OnceCell<StateMapping<StateT, EventT>>,
     logger: HSMLogger,
     // This is risky and could lead to us getting stuck!
     // These are events that are queued up while handling other events
@@ -39,94 +52,202 @@ pub(crate) struct HSMEngine<StateT: StateConstraint, EventT: StateEventConstrain
     /// Why important? What if in handle_event, a state tells their controller to dispatch an event back at us?
     /// We use this to know that the event should be queued up.
     in_progress_event_name: RefCell<Option<String>>,
+    /// The state whose handler (handle_event, exit, enter or start) is running.
+    /// Source of the changes of state it requests.
+    handling_state: Cell<Option<StateT>>,
+    /// Only Some while a consumer asked for a report of the current dispatch.
+    /// One entry per event handled (dispatched event first, then internal events).
+    dispatch_reports: RefCell<Option<Vec<DispatchReport<StateT>>>>,
+    observers: RefCell<Vec<SharedObserver<StateT, EventT>>>,
+    /// Always-on history of the most recently handled events
+    flight_recorder: RefCell<FlightRecorder<StateT>>,
+    /// Snapshot of the flight recorder taken when the last error was raised while dispatching
+    /// Also registered as an observer - that is how it is fed.
+    #[cfg(feature = "metrics")]
+    metrics: Rc<HsmMetrics>,
+    /// Time source for everything time-in-state related
+    clock: RefCell<SharedClock>,
+    residency: RefCell<ResidencyTracker<StateT>>,
+    /// Only Some while a consumer is recording a chrome trace
+    chrome_trace: RefCell<Option<ChromeTraceRecorder>>,
     phantom_state_enum: PhantomData<StateT>,
 }
 
 pub(crate) type SharedEngine<StateT, EventT> = Rc<HSMEngine<StateT, EventT>>;
+#[allow(dead_code)]
 pub(crate) type WeakEngine<StateT, EventT> = Weak<HSMEngine<StateT, EventT>>;
 
 impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, EventT> {
     /// Create an HSM engine.
     /// Highly recommend NOT exposing the HSMEngine beyond your container.
     /// Will need to be built up after the fact - via the builder!
-    fn new(
-        hsm_name: String,
-        logger_level: LevelFilter,
-    ) -> HSMResult<SharedEngine<StateT, EventT>, StateT> {
+    pub(crate) fn new(hsm_name: String, logger: HSMLogger) -> SharedEngine<StateT, EventT> {
+        let logger = logger.with_hsm_name(hsm_name.as_str());
+        #[cfg(feature = "metrics")]
+        let metrics = Rc::new(HsmMetrics::new(hsm_name.as_str()));
         let engine = HSMEngine {
             hsm_name,
             current_state: Cell::new(None),
-            current_handle_string: RefCell::new(String::new()),
-            state_mapping: RefCell::new(StateMapping::<StateT, EventT>::new_default()),
-            logger: HSMLogger::new(logger_level),
+            current_trace: RefCell::new(TraceRecord::default()),
+            state_mapping: OnceCell::new(),
+            logger,
             pending_events: Default::default(),
             phantom_state_enum: PhantomData,
             already_changed_state: Cell::new(false),
             in_progress_event_name: RefCell::new(None),
+            handling_state: Cell::new(None),
+            dispatch_reports: RefCell::new(None),
+            #[cfg(not(feature = "metrics"))]
+            observers: RefCell::new(vec![]),
+            #[cfg(feature = "metrics")]
+            observers: RefCell::new(vec![metrics.clone()]),
+            flight_recorder: RefCell::new(FlightRecorder::new(DEFAULT_HISTORY_CAPACITY)),
+            #[cfg(feature = "metrics")]
+            metrics,
+            clock: RefCell::new(Rc::new(SystemClock)),
+            residency: Default::default(),
+            chrome_trace: RefCell::new(None),
         };
-        Ok(Rc::new(engine))
+        Rc::new(engine)
     }
 
+    #[allow(dead_code)]
     pub fn get_delegate(this: &Rc<Self>) -> Rc<Self> {
         this.clone()
     }
 
-    // Hide state ID's from users!
-    /// Add the relationship between 2 states based on their id's.
-    /// We have no knowledge of the state objects themselves.
-    /// Helps us de-couple adding a state to the engine vs creating the states.
-    pub fn add_state<T: Display + Into<u16> + From<u16>>(
-        &self,
-        new_state: StateBox<StateT, EventT>,
-        new_state_metadata: T,
-        parent_state: Option<T>,
-    ) -> HSMResult<(), StateT> {
-        let new_state_id = StateId::new(new_state_metadata.into());
-        self.state_mapping
-            .borrow_mut()
-            .add_state_internal(new_state_id, parent_state)?;
+    /// The validated topology. Only missing while the HSM is still being built.
+    fn get_state_mapping(&self) -> HSMResult<&StateMapping<StateT, EventT>, StateT> {
         self.state_mapping
-            .borrow_mut()
-            .transfer_state(new_state, new_state_id)
+            .get()
+            .ok_or(HSMError::EngineNotInitialized())
     }
 
-    /// Initializes the HSM - required before use!
-    pub fn init(&self, starting_state: u16) -> HSMResult<(), StateT> {
-        self.state_mapping.borrow().validate_cross_states()?;
+    pub fn add_observer(&self, observer: SharedObserver<StateT, EventT>) {
+        self.observers.borrow_mut().push(observer);
+    }
 
-        let initial_state_struct = StateId::from(starting_state);
-        match self
-            .state_mapping
-            .borrow()
-            .is_state_id_valid(&initial_state_struct)
-        {
-            true => Ok(()),
-            false => Err(HSMError::InvalidStateId(
-                StateT::from(starting_state),
+    /// Takes ownership of the (validated) topology and enters the starting state.
+    /// Only called once, by the builder.
+    pub(crate) fn init(
+        &self,
+        state_mapping: StateMapping<StateT, EventT>,
+        starting_state: StateT,
+    ) -> HSMResult<(), StateT> {
+        self.init_internal(state_mapping, starting_state)
+            .inspect_err(|err| self.notify_observers(|observer| observer.on_error(err)))
+    }
+
+    fn init_internal(
+        &self,
+        mut state_mapping: StateMapping<StateT, EventT>,
+        starting_state: StateT,
+    ) -> HSMResult<(), StateT> {
+        if !state_mapping.is_state_id_valid(&starting_state) {
+            return Err(HSMError::InvalidStateId(
+                starting_state,
                 get_function_name!(),
-            )),
-        }?;
+                vec![],
+            ));
+        }
+        state_mapping.set_initial_state(starting_state);
+        let starting_state = state_mapping.resolve_initial_state(starting_state);
+        if self.state_mapping.set(state_mapping).is_err() {
+            return Err(HSMError::GenericError(
+                "The HSM was already initialized!".to_string(),
+            ));
+        }
+
+        let initial_state_struct = starting_state;
         self.logger.log_info(
             get_function_name!(),
-            format!(
-                "Initial State: {}",
-                StateT::from(*initial_state_struct.get_id())
-            )
-            .as_str(),
+            format!("Initial State: {}", initial_state_struct).as_str(),
         );
-        self.current_state.set(Some(initial_state_struct));
+        self.set_current_state(&initial_state_struct)?;
         self.enter_states_lca_to_target(initial_state_struct, true)
     }
 
     pub fn get_current_state(&self) -> HSMResult<StateT, StateT> {
-        let state: StateT = (*self
+        self.current_state
+            .get()
+            .ok_or(HSMError::EngineNotInitialized())
+    }
+
+    pub fn is_in_state(&self, state: StateT) -> bool {
+        match (self.current_state.get(), self.state_mapping.get()) {
+            (Some(current_state_id), Some(state_mapping)) => state_mapping
+                .resolve_path_to_root(&current_state_id)
+                .map(|path| path.contains(&state))
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    pub fn active_configuration(&self) -> HSMResult<Vec<StateT>, StateT> {
+        let current_state_id = self
             .current_state
             .get()
-            .ok_or_else(|| HSMError::EngineNotInitialized())?
-            .to_owned()
-            .get_id())
-        .into();
-        Ok(state)
+            .ok_or(HSMError::EngineNotInitialized())?;
+        let path_to_root = self
+            .get_state_mapping()?
+            .resolve_path_to_root(&current_state_id)?;
+        Ok(path_to_root.iter().rev().copied().collect())
+    }
+
+    pub fn parent_of(&self, state: StateT) -> HSMResult<Option<StateT>, StateT> {
+        let state_mapping = self.get_state_mapping()?;
+        state_mapping.is_state_id_valid_result(&state)?;
+        Ok(state_mapping.get_parent_state_id(&state))
+    }
+
+    pub fn children_of(&self, state: StateT) -> HSMResult<Vec<StateT>, StateT> {
+        let state_mapping = self.get_state_mapping()?;
+        state_mapping.is_state_id_valid_result(&state)?;
+        Ok(state_mapping.get_children_state_ids(&state))
+    }
+
+    pub fn all_states(&self) -> Vec<StateT> {
+        self.state_mapping
+            .get()
+            .map(|state_mapping| state_mapping.get_all_state_ids())
+            .unwrap_or_default()
+    }
+
+    pub fn transition_registry(&self) -> TransitionRegistry<StateT> {
+        self.state_mapping
+            .get()
+            .map(|state_mapping| state_mapping.get_transitions().clone())
+            .unwrap_or_default()
+    }
+
+    pub fn to_dot(&self, highlight_current_state: bool) -> HSMResult<String, StateT> {
+        let highlighted_state = match highlight_current_state {
+            true => Some(self.get_current_state()?),
+            false => None,
+        };
+        Ok(to_dot(self.get_state_mapping()?, highlighted_state))
+    }
+
+    pub(crate) fn to_state_diagram(&self, dialect: DiagramDialect) -> HSMResult<String, StateT> {
+        Ok(to_state_diagram(self.get_state_mapping()?, dialect))
+    }
+
+    #[cfg(feature = "scxml")]
+    pub fn to_scxml(&self) -> HSMResult<String, StateT> {
+        to_scxml(self.get_state_mapping()?)
+    }
+
+    pub fn depth_of(&self, state: StateT) -> HSMResult<usize, StateT> {
+        self.get_state_mapping()?.get_depth(&state)
+    }
+
+    pub fn lca(&self, a: StateT, b: StateT) -> HSMResult<StateT, StateT> {
+        let state_mapping = self.get_state_mapping()?;
+        state_mapping.is_state_id_valid_result(&a)?;
+        if a == b {
+            return Ok(a);
+        }
+        state_mapping.find_lca(&a, &b)
     }
 
     /// Send an event into the HSM from within the HSM.
@@ -138,31 +259,34 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
         let event_start_state_id = self
             .current_state
             .get()
-            .ok_or_else(|| HSMError::EngineNotInitialized())?;
+            .ok_or(HSMError::EngineNotInitialized())?;
 
         // Validate the current state can handle events / is in the mapping
         match self
-            .state_mapping
-            .borrow()
+            .get_state_mapping()?
             .is_state_valid(&event_start_state_id)
         {
             false => Err(HSMError::InvalidStateId(
-                StateT::from(event_start_state_id.get_id().to_owned()),
+                event_start_state_id,
                 get_function_name!(),
+                vec![],
             )),
             true => Ok(()),
         }?;
 
-        let hsm_name = self.get_hsm_name();
-        self.clear_handle_string();
-        self.update_handle_string(
-            format!(
-                "{}: {}({}): ",
-                hsm_name,
-                resolve_state_name::<StateT>(&event_start_state_id),
-                &event
-            )
-            .as_str(),
+        let event_span_start = self.chrome_span_start();
+        *self.current_trace.borrow_mut() = TraceRecord {
+            hsm_name: self.get_hsm_name(),
+            state: event_start_state_id.to_string(),
+            event: event.to_string(),
+            ..Default::default()
+        };
+
+        self.start_report(event.get_event_name(), event_start_state_id);
+        self.flight_recorder.borrow_mut().record_event(
+            event.get_event_name(),
+            event_start_state_id,
+            self.wall_time(),
         );
 
         let mut current_state_id = event_start_state_id.to_owned();
@@ -171,38 +295,61 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
             let event_name = event.get_event_name().clone();
             *self.in_progress_event_name.borrow_mut() = Some(event_name.clone());
             // TODO - if the StateEventConstraint allowed an optional override to translate the args to display, this would be more useful
-            // self.update_handle_string(format!("{}()", event_name).as_str());
-            // self.update_handle_string("");
 
-            let is_handled = self
-                .state_mapping
-                .borrow()
-                .handle_event(&current_state_id, &event)?;
+            let offered_state = current_state_id;
+            self.notify_observers(|observer| observer.on_event_offered(&offered_state, &event));
+
+            let handler_span_start = self.chrome_span_start();
+            let state_mapping = self.get_state_mapping()?;
+            let is_handled = self.as_handling_state(offered_state, || {
+                state_mapping.handle_event(&current_state_id, &event)
+            })?;
+            self.chrome_span_end(handler_span_start, "handle_event", || {
+                (
+                    offered_state.to_string(),
+                    vec![
+                        ("event".to_string(), event.get_event_name()),
+                        ("handled".to_string(), is_handled.to_string()),
+                    ],
+                )
+            });
+
+            self.update_report(|report| {
+                let state = current_state_id;
+                report.offers.push(StateOffer {
+                    state,
+                    handled: is_handled,
+                });
+                if is_handled {
+                    report.handled_by = Some(state);
+                }
+            });
 
             if is_handled {
+                self.notify_observers(|observer| observer.on_event_handled(&offered_state, &event));
+                self.flight_recorder
+                    .borrow_mut()
+                    .update_current(|entry| entry.handled_by = Some(offered_state));
                 break;
             }
             self.logger.log_debug(
                 get_function_name!(),
-                format!(
-                    "{} Handling Event {}",
-                    resolve_state_name::<StateT>(&current_state_id),
-                    event_name,
-                )
-                .as_str(),
+                format!("{} Handling Event {}", current_state_id, event_name,).as_str(),
             );
 
             let next_state_id = match self
-                .state_mapping
-                .borrow()
+                .get_state_mapping()?
                 .get_parent_state_id(&current_state_id)
             {
-                None => break, // Reached Top State
+                None => {
+                    // Reached Top State
+                    self.notify_observers(|observer| observer.on_event_unhandled(&event));
+                    break;
+                }
                 Some(next_id) => next_id,
             };
 
-            self.state_mapping
-                .borrow()
+            self.get_state_mapping()?
                 .is_state_id_valid_result(&next_state_id)?;
 
             self.logger.log_debug(
@@ -210,7 +357,7 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
                 format!(
                     "Letting Parent State Handle the event: {}({})",
                     event.get_event_name(),
-                    utils::resolve_state_name::<StateT>(&next_state_id),
+                    next_state_id,
                 )
                 .as_str(),
             );
@@ -221,6 +368,17 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
 
         // If we get here, the event has been handled by at least one state (or none and we error'd)
         *self.in_progress_event_name.borrow_mut() = None;
+        let final_state = self.get_current_state()?;
+        self.update_report(|report| report.final_state = final_state);
+        self.flight_recorder
+            .borrow_mut()
+            .update_current(|entry| entry.completed_at = Some(self.wall_time()));
+        self.chrome_span_end(event_span_start, "event", || {
+            (
+                event.get_event_name(),
+                vec![("state".to_string(), event_start_state_id.to_string())],
+            )
+        });
 
         // Check for pending events! Doing this ensures we will always handle all pending events!
         // TODO - Is there a way we could do this asynchronously / non-recursively?
@@ -231,46 +389,45 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
         }
     }
 
-    pub fn get_state_name(&self, state_id: &u16) -> HSMResult<String, StateT> {
-        let name = StateT::from(*state_id).to_string();
-        Ok(name)
-    }
-
     /// # Brief
     /// Exit all states from [current->LCA) and enter (LCA->target]
     /// THEN handle start on target.
     /// # NOTE
     /// CHANGE STATES ARE ENQUEUED via delegate!
-    fn handle_state_change(&self, requested_state: StateId) -> HSMResult<(), StateT> {
+    fn handle_state_change(&self, requested_state: StateT) -> HSMResult<(), StateT> {
+        let state_mapping = self.get_state_mapping()?;
+        state_mapping.is_state_id_valid_result(&requested_state)?;
+        // Composite states hand over to their initial (or remembered) children
+        let requested_state = state_mapping.resolve_initial_state(requested_state);
         let is_target_current = self.current_state.get() == Some(requested_state);
 
         // We don't clear requests once completed - requires too much mutable access
         // Just no-op on all subsequent events
         if is_target_current {
+            self.already_changed_state.set(false);
             return Ok(());
         }
 
-        let target_state_id = {
-            let target_state: StateT = self
-                .state_mapping
-                .borrow()
-                .is_state_id_valid_result(&requested_state)
-                .map(|_| StateT::from(*requested_state.get_id()))?;
-            StateId::new(target_state.into())
-        };
-
-        assert!(
-            self.state_mapping
-                .borrow()
-                .is_state_id_valid(&requested_state),
-            "State with id {} invalid! ",
-            requested_state.get_id()
-        );
+        let target_state_id = requested_state;
+
+        let source_state = self.get_current_state()?;
+        let target_state = target_state_id;
+        #[cfg(feature = "tracing")]
+        let _transition_span = tracing::info_span!(
+            "hsm_transition",
+            hsm = %self.hsm_name,
+            source = %source_state,
+            target = %target_state,
+        )
+        .entered();
+        self.notify_observers(|observer| {
+            observer.on_transition_begin(&source_state, &target_state)
+        });
 
         let lca_state_id = self.find_lca(
             self.current_state
                 .get()
-                .ok_or_else(|| HSMError::EngineNotInitialized())?,
+                .ok_or(HSMError::EngineNotInitialized())?,
             requested_state,
         )?;
 
@@ -278,7 +435,7 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
             != self
                 .current_state
                 .get()
-                .ok_or_else(|| HSMError::EngineNotInitialized())?
+                .ok_or(HSMError::EngineNotInitialized())?
         {
             self.exit_states_until_target(lca_state_id)?;
         }
@@ -287,30 +444,32 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
 
         self.set_current_state(&target_state_id)?;
         self.handle_event_complete();
+        self.flight_recorder.borrow_mut().update_current(|entry| {
+            entry.transitions.push(RecordedTransition {
+                source: source_state,
+                target: target_state,
+            })
+        });
+        self.notify_observers(|observer| observer.on_transition_end(&source_state, &target_state));
 
         Ok(())
     }
 
     /// get LCA between current state and other state
-    fn find_lca(&self, source_state: StateId, target_state: StateId) -> HSMResult<StateId, StateT> {
-        self.state_mapping
-            .borrow()
+    fn find_lca(&self, source_state: StateT, target_state: StateT) -> HSMResult<StateT, StateT> {
+        self.get_state_mapping()?
             .find_lca(&source_state, &target_state)
     }
 
     /// Exits all states along the path to target (not including target)
-    fn exit_states_until_target(&self, target_state_id: StateId) -> HSMResult<(), StateT> {
-        self.update_handle_string("[");
-        let mut exited_first_state = false;
-
+    fn exit_states_until_target(&self, target_state_id: StateT) -> HSMResult<(), StateT> {
         let mut current_state_id = self.current_state.get();
         match current_state_id {
             Some(_) => Ok(()),
             None => Err(HSMError::EngineNotInitialized()),
         }?;
 
-        self.state_mapping
-            .borrow()
+        self.get_state_mapping()?
             .is_state_id_valid_result(&current_state_id.unwrap())?;
 
         loop {
@@ -322,34 +481,39 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
                         // Once we reach the LCA/target, stop exiting
                         break;
                     }
-                    self.state_mapping
-                        .borrow()
+                    self.get_state_mapping()?
                         .is_state_id_valid_result(&state_id)?
                 }
             };
             let unwrapped_id = current_state_id.unwrap();
 
-            let current_state_name = resolve_state_name::<StateT>(&unwrapped_id);
-
-            if exited_first_state {
-                self.update_handle_string(", ");
-            }
+            let current_state_name = unwrapped_id.to_string();
 
-            self.update_handle_string(format!("{}(EXIT)", current_state_name).as_str());
+            self.current_trace
+                .borrow_mut()
+                .exited
+                .push(current_state_name);
+            self.record_step(&unwrapped_id, TransitionAction::Exit);
+            let exited_state = unwrapped_id;
+            self.notify_observers(|observer| observer.on_state_exited(&exited_state));
 
             // current_state_container.state_ref.handle_state_exit();
-            self.state_mapping
-                .borrow()
-                .handle_state_exit(&unwrapped_id)?;
-
-            let next_state_id = self.state_mapping.borrow().get_parent_state_id(
+            let exit_span_start = self.chrome_span_start();
+            let state_mapping = self.get_state_mapping()?;
+            self.as_handling_state(unwrapped_id, || {
+                state_mapping.handle_state_exit(&unwrapped_id)
+            })?;
+            self.get_state_mapping()?.record_exit(unwrapped_id);
+            self.chrome_span_end(exit_span_start, "exit", || {
+                (exited_state.to_string(), vec![])
+            });
+
+            let next_state_id = self.get_state_mapping()?.get_parent_state_id(
                 &current_state_id.expect("Already break'd if this wasn't true!"),
             );
             current_state_id = next_state_id;
-            exited_first_state = true;
         }
 
-        self.update_handle_string("], ");
         Ok(())
     }
 
@@ -357,14 +521,13 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
     /// Starts the target state
     fn enter_states_lca_to_target(
         &self,
-        target_state_id: StateId,
+        target_state_id: StateT,
         is_init_enter: bool,
     ) -> HSMResult<(), StateT> {
-        let target_to_lca_path: Vec<StateId> = self
-            .state_mapping
-            .borrow()
+        let target_to_lca_path: Vec<StateT> = self
+            .get_state_mapping()?
             .resolve_path_to_root(&target_state_id)?;
-        let target_state = StateT::from(*target_state_id.get_id());
+        let target_state = target_state_id;
         let target_state_name = target_state.to_string();
 
         let mut lca_to_target_path = target_to_lca_path.into_iter().rev();
@@ -373,45 +536,159 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
             lca_to_target_path.next();
         }
 
-        self.update_handle_string("[");
-
         for entering_state_id in lca_to_target_path {
-            self.state_mapping
-                .borrow()
-                .handle_state_enter(&entering_state_id)?;
-
-            let state_to_enter_name = resolve_state_name::<StateT>(&entering_state_id);
+            let enter_span_start = self.chrome_span_start();
+            let state_mapping = self.get_state_mapping()?;
+            self.as_handling_state(entering_state_id, || {
+                state_mapping.handle_state_enter(&entering_state_id)
+            })?;
+            self.chrome_span_end(enter_span_start, "enter", || {
+                (entering_state_id.to_string(), vec![])
+            });
+
+            let state_to_enter_name = entering_state_id.to_string();
             self.logger.log_trace(
                 get_function_name!(),
                 format!("Entering {}", state_to_enter_name).as_str(),
             );
-            self.update_handle_string(format!("{}(ENTER), ", state_to_enter_name).as_str());
+            self.current_trace
+                .borrow_mut()
+                .entered
+                .push(state_to_enter_name);
+            self.record_step(&entering_state_id, TransitionAction::Enter);
+            let entered_state = entering_state_id;
+            self.notify_observers(|observer| observer.on_state_entered(&entered_state));
         }
 
         // Start the target state!
-        self.state_mapping
-            .borrow()
-            .handle_state_start(&target_state_id)?;
+        let start_span_start = self.chrome_span_start();
+        let state_mapping = self.get_state_mapping()?;
+        self.as_handling_state(target_state_id, || {
+            state_mapping.handle_state_start(&target_state_id)
+        })?;
+        self.chrome_span_end(start_span_start, "start", || {
+            (target_state.to_string(), vec![])
+        });
         self.logger.log_trace(
             get_function_name!(),
             format!("Starting {}", target_state_name).as_str(),
         );
-        self.update_handle_string(format!("{}(START)]", target_state_name).as_str());
+        self.current_trace.borrow_mut().started = Some(target_state_name);
+        self.record_step(&target_state_id, TransitionAction::Start);
+        self.notify_observers(|observer| observer.on_state_started(&target_state));
         Ok(())
     }
 
+    /// Run one of the state's handlers (handle_event, exit, enter or start),
+    /// making it the source of any change of state requested meanwhile.
+    fn as_handling_state<T, F: FnOnce() -> T>(&self, state: StateT, handler: F) -> T {
+        let previous_state = self.handling_state.replace(Some(state));
+        let result = handler();
+        self.handling_state.set(previous_state);
+        result
+    }
+
     /// Operations to be performed after handling an event, regardless of outcome!
     fn handle_event_complete(&self) {
-        // Log the current chain and reset the message
-        self.logger.log_info(
-            get_function_name!(),
-            self.current_handle_string.borrow().as_str(),
-        );
-        self.clear_handle_string();
+        // Log the current chain and reset the trace
+        let trace = self.current_trace.take();
+        self.logger.log_dispatch_trace(get_function_name!(), &trace);
     }
 
     /// Main API for consumers of the HSM to fire events into it.
     pub fn dispatch_event(&self, event: EventT) -> HSMResult<(), StateT> {
+        #[cfg(feature = "tracing")]
+        let _dispatch_span = tracing::info_span!(
+            "hsm_dispatch",
+            hsm = %self.hsm_name,
+            event = %event.get_event_name(),
+            state = %self
+                .get_current_state()
+                .map(|state| state.to_string())
+                .unwrap_or_else(|_| "Uninitialized".to_string()),
+        )
+        .entered();
+        self.notify_observers(|observer| observer.on_event_dispatched(&event));
+        let dispatch_span_start = self.chrome_span_start();
+        let event_name = event.get_event_name();
+        let dispatch_res = self
+            .dispatch_event_internal(event)
+            .map_err(|err| self.raise_dispatch_error(err));
+        self.chrome_span_end(dispatch_span_start, "dispatch", || {
+            (
+                event_name,
+                vec![("ok".to_string(), dispatch_res.is_ok().to_string())],
+            )
+        });
+        dispatch_res
+    }
+
+    /// Start recording a chrome trace. Any trace already being recorded is discarded.
+    pub fn start_chrome_trace(&self) {
+        let origin = self.now();
+        *self.chrome_trace.borrow_mut() = Some(ChromeTraceRecorder::new(origin));
+    }
+
+    /// Stop recording. None if no trace was being recorded.
+    pub fn stop_chrome_trace(&self) -> Option<ChromeTrace> {
+        self.chrome_trace
+            .borrow_mut()
+            .take()
+            .map(|recorder| recorder.finish(self.get_hsm_name()))
+    }
+
+    /// When recording a chrome trace, the time a span started (None when not recording)
+    fn chrome_span_start(&self) -> Option<SpanStart> {
+        if self.chrome_trace.borrow().is_none() {
+            return None;
+        }
+        let now = self.now();
+        self.chrome_trace
+            .borrow_mut()
+            .as_mut()
+            .map(|recorder| recorder.begin(now))
+    }
+
+    /// Record a span started by chrome_span_start.
+    /// describe is only called when recording, and returns the name and args of the span.
+    fn chrome_span_end<F: FnOnce() -> (String, Vec<(String, String)>)>(
+        &self,
+        span_start: Option<SpanStart>,
+        category: &'static str,
+        describe: F,
+    ) {
+        let Some(span_start) = span_start else {
+            return;
+        };
+        let span_end = self.now();
+        if let Some(recorder) = self.chrome_trace.borrow_mut().as_mut() {
+            let (name, args) = describe();
+            recorder.record(name, category, span_start, span_end, args);
+        }
+    }
+
+    /// Most recently handled events (oldest -> newest)
+    pub fn recent_history(&self) -> Vec<HistoryEntry<StateT>> {
+        self.flight_recorder.borrow().entries()
+    }
+
+    pub fn set_history_capacity(&self, capacity: usize) {
+        self.flight_recorder.borrow_mut().set_capacity(capacity);
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Attach our recent history to an error raised while dispatching and let observers know about it.
+    fn raise_dispatch_error(&self, err: HSMError<StateT>) -> HSMError<StateT> {
+        let err = err.with_history(self.recent_history());
+        self.notify_observers(|observer| observer.on_error(&err));
+        err
+    }
+
+    fn dispatch_event_internal(&self, event: EventT) -> HSMResult<(), StateT> {
         let no_event_in_progress = self.in_progress_event_name.borrow().is_none();
         if no_event_in_progress {
             return self.handle_event_internally(event);
@@ -421,6 +698,7 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
 
         if pending_events_during_handle {
             // We are in the middle of handling another event and somehow a state asked their controller to handle_event
+            self.notify_observers(|observer| observer.on_event_enqueued(&event));
             self.pending_events.borrow_mut().push(event);
             Ok(())
         } else {
@@ -428,18 +706,105 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
         }
     }
 
-    fn set_current_state(&self, new_current_state: &StateId) -> HSMResult<(), StateT> {
+    /// Same as dispatch_event, but reports everything done while handling the event.
+    pub fn dispatch_event_with_report(
+        &self,
+        event: EventT,
+    ) -> HSMResult<DispatchReport<StateT>, StateT> {
+        if self.in_progress_event_name.borrow().is_some() {
+            return Err(HSMError::GenericError(format!(
+                "Cannot report on {} while another event is being handled!",
+                event.get_event_name()
+            )));
+        }
+
+        *self.dispatch_reports.borrow_mut() = Some(vec![]);
+        let dispatch_res = self.dispatch_event(event);
+        let reports = self.dispatch_reports.borrow_mut().take();
+        dispatch_res?;
+
+        let mut reports = reports.unwrap_or_default().into_iter();
+        let mut report = reports.next().ok_or_else(|| {
+            HSMError::GenericError("No report was recorded for the dispatched event".to_string())
+        })?;
+        report.internal_events = reports.collect();
+        report.final_state = self.get_current_state()?;
+        Ok(report)
+    }
+
+    /// Begin reporting on a new event (if anyone asked for a report)
+    fn start_report(&self, event_name: String, start_state: StateT) {
+        if let Some(reports) = self.dispatch_reports.borrow_mut().as_mut() {
+            reports.push(DispatchReport::new(event_name, start_state));
+        }
+    }
+
+    /// Modify the report of the event currently being handled (if anyone asked for a report)
+    fn update_report<F: FnOnce(&mut DispatchReport<StateT>)>(&self, update: F) {
+        if let Some(report) = self
+            .dispatch_reports
+            .borrow_mut()
+            .as_mut()
+            .and_then(|reports| reports.last_mut())
+        {
+            update(report);
+        }
+    }
+
+    fn record_step(&self, state_id: &StateT, action: TransitionAction) {
+        self.update_report(|report| {
+            report.steps.push(TransitionStep {
+                state: *state_id,
+                action,
+            })
+        });
+    }
+
+    /// # Note
+    /// Observers are borrowed while being notified. Registering an observer from within a callback will panic!
+    fn notify_observers<F: Fn(&dyn HsmObserver<StateT, EventT>)>(&self, notify: F) {
+        for observer in self.observers.borrow().iter() {
+            notify(observer.as_ref());
+        }
+    }
+
+    fn set_current_state(&self, new_current_state: &StateT) -> HSMResult<(), StateT> {
         self.current_state.set(Some(*new_current_state));
         self.already_changed_state.set(false);
+        self.residency
+            .borrow_mut()
+            .state_entered(*new_current_state, self.now());
         Ok(())
     }
 
-    fn update_handle_string(&self, append_str: &str) {
-        self.current_handle_string.borrow_mut().push_str(append_str);
+    fn now(&self) -> std::time::Instant {
+        self.clock.borrow().now()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        self.clock.borrow().wall_time()
+    }
+
+    pub fn set_clock(&self, clock: SharedClock) {
+        #[cfg(feature = "metrics")]
+        self.metrics.set_clock(clock.clone());
+        *self.clock.borrow_mut() = clock;
     }
 
-    fn clear_handle_string(&self) {
-        self.current_handle_string.borrow_mut().clear();
+    pub fn time_in_current_state(&self) -> HSMResult<Duration, StateT> {
+        self.residency
+            .borrow()
+            .time_in_current_state(self.now())
+            .ok_or(HSMError::EngineNotInitialized())
+    }
+
+    pub fn state_residency(&self, state: StateT) -> HSMResult<StateResidency, StateT> {
+        self.get_state_mapping()?.is_state_id_valid_result(&state)?;
+        Ok(self.residency.borrow().residency(&state, self.now()))
+    }
+
+    pub fn reset_residency(&self) {
+        self.residency.borrow_mut().reset(self.now());
     }
 
     fn get_hsm_name(&self) -> String {
@@ -450,23 +815,40 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> HSMEngine<StateT, Ev
 impl<StateT: StateConstraint, EventT: StateEventConstraint> EngineDelegateIF<StateT, EventT>
     for HSMEngine<StateT, EventT>
 {
-    fn change_state(&self, new_state: u16) -> HSMResult<(), StateT> {
+    fn change_state(&self, new_state: StateT) -> HSMResult<(), StateT> {
+        // Outside of any handler, the current state is the one asking
+        let source_state = self.handling_state.get().or(self.current_state.get());
+        if let (Some(source_state), Some(state_mapping)) = (source_state, self.state_mapping.get())
+        {
+            if !state_mapping
+                .get_transitions()
+                .is_allowed(source_state, new_state)
+            {
+                return Err(self.raise_dispatch_error(HSMError::IllegalTransition(
+                    source_state,
+                    new_state,
+                    vec![],
+                )));
+            }
+        }
+        // Validate before we count it as a change of state, so a bad request does not block the next one
+        self.get_state_mapping()
+            .and_then(|state_mapping| state_mapping.is_state_id_valid_result(&new_state))
+            .map_err(|err| self.raise_dispatch_error(err))?;
         let current_event_name = match self.in_progress_event_name.borrow().as_ref() {
             None => String::from("Unknown"),
             Some(name) => name.clone(),
         };
         if self.already_changed_state.get() {
             let err = HSMError::MultipleConcurrentChangeState(
-                StateT::from(new_state),
-                StateT::from(
-                    *self
-                        .current_state
-                        .get()
-                        .ok_or_else(|| HSMError::EngineNotInitialized())?
-                        .get_id(),
-                ),
+                new_state,
+                self.current_state
+                    .get()
+                    .ok_or(HSMError::EngineNotInitialized())?,
                 current_event_name.to_string(),
+                vec![],
             );
+            let err = self.raise_dispatch_error(err);
             if cfg!(test) {
                 panic!("{}", err);
             } else {
@@ -474,7 +856,11 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> EngineDelegateIF<Sta
             }
         }
         self.already_changed_state.set(true);
-        self.handle_state_change(StateId::from(new_state))
+        self.handle_state_change(new_state).map_err(|err| {
+            // Only a completed change of state clears the flag (see set_current_state)
+            self.already_changed_state.set(false);
+            self.raise_dispatch_error(err)
+        })
     }
 
     fn internal_handle_event(&self, event: EventT) -> HSMResult<(), StateT> {
@@ -491,9 +877,26 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> EngineDelegateIF<Sta
             )
             .as_str(),
         );
+        self.notify_observers(|observer| observer.on_event_enqueued(&event));
         self.pending_events.borrow_mut().push(event);
         Ok(())
     }
+
+    fn current_state(&self) -> HSMResult<StateT, StateT> {
+        self.get_current_state()
+    }
+
+    fn in_progress_event_name(&self) -> Option<String> {
+        self.in_progress_event_name.borrow().clone()
+    }
+
+    fn is_in(&self, state: StateT) -> bool {
+        self.is_in_state(state)
+    }
+
+    fn pending_event_count(&self) -> usize {
+        self.pending_events.borrow().len()
+    }
 }
 
 /// # Brief
@@ -506,13 +909,9 @@ pub struct HSM<StateT: StateConstraint, EventT: StateEventConstraint> {
 impl<StateT: StateConstraint + 'static, EventT: StateEventConstraint + 'static>
     HSM<StateT, EventT>
 {
-    pub fn new(
-        hsm_name: String,
-        logger_level: LevelFilter,
-    ) -> HSMResult<HSM<StateT, EventT>, StateT> {
-        Ok(Self {
-            engine: HSMEngine::new(hsm_name, logger_level)?,
-        })
+    /// Only the builder creates HSMs - see HsmBuilder
+    pub(crate) fn new(engine: SharedEngine<StateT, EventT>) -> Self {
+        Self { engine }
     }
 
     pub fn get_delegate(&self) -> WeakDelegate<StateT, EventT> {
@@ -522,36 +921,869 @@ impl<StateT: StateConstraint + 'static, EventT: StateEventConstraint + 'static>
     }
 
     /// # Brief
-    /// Add a state to be used by the HSM
-    pub fn add_state<T: Display + Into<u16> + From<u16>>(
-        &self,
-        new_state: StateBox<StateT, EventT>,
-        new_state_metadata: T,
-        parent_state: Option<T>,
-    ) -> HSMResult<(), StateT> {
-        self.engine
-            .add_state(new_state, new_state_metadata, parent_state)
-    }
-
-    pub fn init(&self, starting_state: u16) -> HSMResult<(), StateT> {
-        self.engine.init(starting_state)
+    /// Register an observer to be called back as the engine handles events.
+    /// Observers are called in the order they were added.
+    pub fn add_observer(&self, observer: SharedObserver<StateT, EventT>) {
+        self.engine.add_observer(observer)
     }
 
     pub fn get_current_state(&self) -> HSMResult<StateT, StateT> {
         self.engine.get_current_state()
     }
+
+    /// # Brief
+    /// True for the current state and all of its ancestors (UML's In()).
+    /// False before init.
+    pub fn is_in_state(&self, state: StateT) -> bool {
+        self.engine.is_in_state(state)
+    }
+
+    /// All active states, from the top state down to the current state
+    pub fn active_configuration(&self) -> HSMResult<Vec<StateT>, StateT> {
+        self.engine.active_configuration()
+    }
+
+    /// None for the top state
+    pub fn parent_of(&self, state: StateT) -> HSMResult<Option<StateT>, StateT> {
+        self.engine.parent_of(state)
+    }
+
+    /// Direct children only
+    pub fn children_of(&self, state: StateT) -> HSMResult<Vec<StateT>, StateT> {
+        self.engine.children_of(state)
+    }
+
+    /// Every state added to the HSM
+    pub fn all_states(&self) -> Vec<StateT> {
+        self.engine.all_states()
+    }
+
+    /// Distance from the top state (the top state is at depth 0)
+    pub fn depth_of(&self, state: StateT) -> HSMResult<usize, StateT> {
+        self.engine.depth_of(state)
+    }
+
+    /// # Brief
+    /// The states each state is allowed to change to (see HsmBuilder::add_state_with_transitions),
+    /// and the events labelling transitions (see HsmBuilder::add_transition_event).
+    /// i.e. for tooling drawing the transition graph
+    pub fn transition_registry(&self) -> TransitionRegistry<StateT> {
+        self.engine.transition_registry()
+    }
+
+    /// # Brief
+    /// Graphviz (DOT) digraph of the HSM: composite states as clusters, leaves as nodes and
+    /// the declared transitions as edges labelled with their events (see HsmBuilder::add_transition_event).
+    /// Render with i.e. `dot -Tsvg`.
+    /// # Arguments
+    /// * `highlight_current_state` - Fill in the current state
+    pub fn to_dot(&self, highlight_current_state: bool) -> HSMResult<String, StateT> {
+        self.engine.to_dot(highlight_current_state)
+    }
+
+    /// # Brief
+    /// PlantUML state diagram of the HSM: nested states, initial states, history and
+    /// the declared transitions labelled with their events.
+    pub fn to_plantuml(&self) -> HSMResult<String, StateT> {
+        self.engine.to_state_diagram(DiagramDialect::PlantUml)
+    }
+
+    /// # Brief
+    /// Same as to_plantuml, as a Mermaid (stateDiagram-v2) diagram. i.e. for Markdown docs
+    pub fn to_mermaid(&self) -> HSMResult<String, StateT> {
+        self.engine.to_state_diagram(DiagramDialect::Mermaid)
+    }
+
+    /// # Brief
+    /// SCXML document of the HSM (hierarchy, initial states, history and the transitions
+    /// declared with their events). Opens in standard statechart viewers and simulators,
+    /// and loads back with scxml::parse_scxml.
+    /// # Errors
+    /// InvalidScxml listing every state with children but no initial child
+    /// (SCXML would enter their first child, where the HSM stops at them)
+    #[cfg(feature = "scxml")]
+    pub fn to_scxml(&self) -> HSMResult<String, StateT> {
+        self.engine.to_scxml()
+    }
+
+    /// # Brief
+    /// Lowest common ancestor of two states.
+    /// If one is an ancestor of the other (or they are the same), it is that state.
+    pub fn lca(&self, a: StateT, b: StateT) -> HSMResult<StateT, StateT> {
+        self.engine.lca(a, b)
+    }
+
+    /// The most recently handled events (oldest -> newest)
+    pub fn recent_history(&self) -> Vec<HistoryEntry<StateT>> {
+        self.engine.recent_history()
+    }
+
+    /// How many events are remembered by recent_history. Defaults to DEFAULT_HISTORY_CAPACITY.
+    pub fn set_history_capacity(&self, capacity: usize) {
+        self.engine.set_history_capacity(capacity)
+    }
+
+    /// # Brief
+    /// Start recording dispatches, handler calls and enter/exit/start hooks (with durations).
+    /// Timestamps come from the engine's clock.
+    pub fn start_chrome_trace(&self) {
+        self.engine.start_chrome_trace()
+    }
+
+    /// # Brief
+    /// Stop recording and get everything recorded since start_chrome_trace.
+    /// Write it out with ChromeTrace::write_to and open it in Perfetto or chrome://tracing.
+    pub fn stop_chrome_trace(&self) -> Option<ChromeTrace> {
+        self.engine.stop_chrome_trace()
+    }
+
+    /// # Brief
+    /// Replace the engine's source of time (i.e. with a ManualClock in tests).
+    /// Also timestamps the recent history.
+    /// Set it before init - time already spent in the current state was measured with the old clock.
+    pub fn set_clock(&self, clock: SharedClock) {
+        self.engine.set_clock(clock)
+    }
+
+    /// How long the machine has been in its current state
+    pub fn time_in_current_state(&self) -> HSMResult<Duration, StateT> {
+        self.engine.time_in_current_state()
+    }
+
+    /// # Brief
+    /// Entry count, cumulative time and last time entered for a state.
+    /// Only counts time the state was the current (leaf) state. Time spent in its children is theirs,
+    /// i.e. a composite state accrues nothing while one of its children is active.
+    pub fn state_residency(&self, state: StateT) -> HSMResult<StateResidency, StateT> {
+        self.engine.state_residency(state)
+    }
+
+    /// Forget all residency so far, i.e. to start a new reporting window.
+    pub fn reset_residency(&self) {
+        self.engine.reset_residency()
+    }
+
+    /// # Brief
+    /// Everything collected about this HSM so far.
+    /// Use MetricsSnapshot::to_prometheus to expose it.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.engine.metrics_snapshot()
+    }
     pub fn dispatch_event(&self, event: EventT) -> HSMResult<(), StateT> {
         self.engine.dispatch_event(event)
     }
+
+    /// # Brief
+    /// Opt-in alternative to dispatch_event.
+    /// Reports which states were offered the event, who handled it, every
+    /// exit/enter/start performed, internal events handled and the final state.
+    pub fn dispatch_event_with_report(
+        &self,
+        event: EventT,
+    ) -> HSMResult<DispatchReport<StateT>, StateT> {
+        self.engine.dispatch_event_with_report(event)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::{
+        builder::HsmBuilder,
+        clock::{HsmClock, ManualClock},
+        examples::{B1Impl, ExampleEvents, ExampleFData, ExampleStates},
+        log_sink::RingBufferSink,
+        state::StateIF,
+        test_utils::{
+            create_test_builder, create_test_builder_with_transitions,
+            create_test_builder_without_b1, create_test_hsm, create_test_hsm_with_logger,
+        },
+        trace_format::TraceFormat,
+    };
+    use log::LevelFilter;
+
+    fn offer(state: ExampleStates, handled: bool) -> StateOffer<ExampleStates> {
+        StateOffer { state, handled }
+    }
+
+    fn step(state: ExampleStates, action: TransitionAction) -> TransitionStep<ExampleStates> {
+        TransitionStep { state, action }
+    }
+
+    #[test]
+    fn dispatch_event_with_report() {
+        let hsm = create_test_hsm();
+
+        // Bubbles up to the parent, no transitions
+        let report = hsm.dispatch_event_with_report(ExampleEvents::A).unwrap();
+        assert_eq!(report.event_name, "A");
+        assert_eq!(report.start_state, ExampleStates::LevelA2);
+        assert_eq!(
+            report.offers,
+            vec![
+                offer(ExampleStates::LevelA2, false),
+                offer(ExampleStates::LevelA1, true)
+            ]
+        );
+        assert_eq!(report.handled_by, Some(ExampleStates::LevelA1));
+        assert!(report.steps.is_empty());
+        assert!(report.internal_events.is_empty());
+        assert_eq!(report.final_state, ExampleStates::LevelA2);
+
+        // Changes state A2 -> B1 through their LCA (Top)
+        let report = hsm
+            .dispatch_event_with_report(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        assert_eq!(report.handled_by, Some(ExampleStates::LevelA2));
+        assert_eq!(
+            report.steps,
+            vec![
+                step(ExampleStates::LevelA2, TransitionAction::Exit),
+                step(ExampleStates::LevelA1, TransitionAction::Exit),
+                step(ExampleStates::LevelB1, TransitionAction::Enter),
+                step(ExampleStates::LevelB1, TransitionAction::Start),
+            ]
+        );
+        assert_eq!(
+            report.states_with_action(TransitionAction::Exit),
+            vec![ExampleStates::LevelA2, ExampleStates::LevelA1]
+        );
+        assert_eq!(report.final_state, ExampleStates::LevelB1);
+
+        // B1 fires an internal event which its parent handles
+        let report = hsm.dispatch_event_with_report(ExampleEvents::E(0)).unwrap();
+        assert_eq!(report.handled_by, Some(ExampleStates::LevelB1));
+        assert_eq!(report.internal_events.len(), 1);
+        let internal_report = &report.internal_events[0];
+        assert_eq!(internal_report.event_name, "A");
+        assert_eq!(
+            internal_report.offers,
+            vec![
+                offer(ExampleStates::LevelB1, false),
+                offer(ExampleStates::Top, true)
+            ]
+        );
+        assert_eq!(internal_report.handled_by, Some(ExampleStates::Top));
+        assert_eq!(report.final_state, ExampleStates::LevelB1);
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl RecordingObserver {
+        fn record(&self, call: String) {
+            self.calls.borrow_mut().push(call);
+        }
+    }
+
+    impl HsmObserver<ExampleStates, ExampleEvents> for RecordingObserver {
+        fn on_event_dispatched(&self, event: &ExampleEvents) {
+            self.record(format!("dispatched {}", event));
+        }
+        fn on_event_offered(&self, state: &ExampleStates, event: &ExampleEvents) {
+            self.record(format!("offered {} to {}", event, state));
+        }
+        fn on_event_handled(&self, state: &ExampleStates, event: &ExampleEvents) {
+            self.record(format!("{} handled {}", state, event));
+        }
+        fn on_event_unhandled(&self, event: &ExampleEvents) {
+            self.record(format!("unhandled {}", event));
+        }
+        fn on_state_exited(&self, state: &ExampleStates) {
+            self.record(format!("exit {}", state));
+        }
+        fn on_state_entered(&self, state: &ExampleStates) {
+            self.record(format!("enter {}", state));
+        }
+        fn on_state_started(&self, state: &ExampleStates) {
+            self.record(format!("start {}", state));
+        }
+        fn on_transition_begin(&self, source: &ExampleStates, target: &ExampleStates) {
+            self.record(format!("begin {} -> {}", source, target));
+        }
+        fn on_transition_end(&self, source: &ExampleStates, target: &ExampleStates) {
+            self.record(format!("end {} -> {}", source, target));
+        }
+        fn on_event_enqueued(&self, event: &ExampleEvents) {
+            self.record(format!("enqueued {}", event));
+        }
+        fn on_error(&self, error: &HSMError<ExampleStates>) {
+            self.record(format!("error {}", error));
+        }
+    }
+
+    #[test]
+    fn observers() {
+        let observer = Rc::new(RecordingObserver::default());
+        let mut builder = create_test_builder();
+        builder.add_observer(observer.clone());
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+        assert_eq!(
+            observer.calls.take(),
+            vec![
+                "enter Top",
+                "enter LevelA1",
+                "enter LevelA2",
+                "start LevelA2"
+            ]
+        );
+
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        assert_eq!(
+            observer.calls.take(),
+            vec![
+                "dispatched F",
+                "offered F to LevelA2",
+                "begin LevelA2 -> LevelB1",
+                "exit LevelA2",
+                "exit LevelA1",
+                "enter LevelB1",
+                "start LevelB1",
+                "end LevelA2 -> LevelB1",
+                "LevelA2 handled F",
+            ]
+        );
+
+        hsm.dispatch_event(ExampleEvents::E(0)).unwrap();
+        assert_eq!(
+            observer.calls.take(),
+            vec![
+                "dispatched E",
+                "offered E to LevelB1",
+                "enqueued A",
+                "LevelB1 handled E",
+                "offered A to LevelB1",
+                "offered A to Top",
+                "Top handled A",
+            ]
+        );
+
+        let mut builder = create_test_builder_without_b1();
+        builder.add_observer(observer.clone());
+        assert!(builder.build(ExampleStates::LevelB1).is_err());
+        let calls = observer.calls.take();
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].starts_with("error State LevelB1 never added"));
+    }
+
+    #[test]
+    fn dispatch_traces_written_to_sinks() {
+        let sink = Rc::new(RingBufferSink::new(10));
+        let logger = HSMLogger::new(LevelFilter::Off)
+            .with_trace_format(TraceFormat::JsonLines)
+            .with_sink(sink.clone());
+        let hsm = create_test_hsm_with_logger(logger);
+
+        // Only events causing a change in state are traced
+        hsm.dispatch_event(ExampleEvents::A).unwrap();
+        assert!(sink.lines().is_empty());
+
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        let lines = sink.lines();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"hsm":"TestHsm","state":"LevelA2","event":"F","exited":["LevelA2","LevelA1"],"entered":["LevelB1"],"started":"LevelB1"}"#
+            ]
+        );
+        let record = TraceFormat::JsonLines.parse(&lines[0]).unwrap();
+        assert_eq!(
+            TraceFormat::Human.format(&record),
+            "TestHsm: LevelA2(F): [LevelA2(EXIT), LevelA1(EXIT)], [LevelB1(ENTER), LevelB1(START)]"
+        );
+    }
+
+    #[test]
+    fn recent_history() {
+        let hsm = create_test_hsm();
+        hsm.set_history_capacity(2);
+
+        hsm.dispatch_event(ExampleEvents::A).unwrap();
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        hsm.dispatch_event(ExampleEvents::E(0)).unwrap();
+
+        // E fires A internally. Only have room for the last 2 events.
+        let history = hsm.recent_history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].event_name, "E");
+        assert_eq!(history[0].source_state, ExampleStates::LevelB1);
+        assert_eq!(history[0].handled_by, Some(ExampleStates::LevelB1));
+        assert_eq!(history[1].event_name, "A");
+        assert_eq!(history[1].handled_by, Some(ExampleStates::Top));
+        assert!(history[1].completed_at.unwrap() >= history[1].started_at);
+
+        hsm.set_history_capacity(5);
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        let history = hsm.recent_history();
+        let last = history.last().unwrap();
+        assert_eq!(last.event_name, "F");
+        // Only A2 changes state on F
+        assert_eq!(last.handled_by, Some(ExampleStates::LevelB1));
+        assert!(last.transitions.is_empty());
+    }
+
+    #[test]
+    fn recent_history_timestamps() {
+        let clock = Rc::new(ManualClock::new());
+        let mut builder = create_test_builder();
+        builder.set_clock(clock.clone());
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+
+        clock.advance(Duration::from_secs(2));
+        hsm.dispatch_event(ExampleEvents::A).unwrap();
+        let history = hsm.recent_history();
+        assert_eq!(
+            history[0].started_at,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(2)
+        );
+        assert_eq!(history[0].completed_at, Some(history[0].started_at));
+    }
+
+    #[test]
+    fn history_kept_for_errors() {
+        let hsm = create_test_builder_without_b1()
+            .build(ExampleStates::LevelA2)
+            .unwrap();
+        hsm.dispatch_event(ExampleEvents::A).unwrap();
+        let delegate = hsm.get_delegate().upgrade().unwrap();
+        let first_error = delegate.change_state(ExampleStates::LevelB1).unwrap_err();
+        hsm.dispatch_event(ExampleEvents::B(0)).unwrap();
+        let second_error = delegate.change_state(ExampleStates::LevelB1).unwrap_err();
+
+        // Errors keep their own variant, and the history as it was when each was raised
+        assert!(matches!(
+            first_error,
+            HSMError::InvalidStateId(ExampleStates::LevelB1, _, _)
+        ));
+        let event_names = |error: &HSMError<ExampleStates>| -> Vec<String> {
+            error
+                .history()
+                .iter()
+                .map(|entry| entry.event_name.clone())
+                .collect()
+        };
+        assert_eq!(event_names(&first_error), ["A"]);
+        assert_eq!(event_names(&second_error), ["A", "B"]);
+        assert_eq!(second_error.history(), hsm.recent_history());
+
+        // Nothing to attach outside of dispatching
+        assert!(create_test_builder_without_b1()
+            .build(ExampleStates::LevelB1)
+            .err()
+            .unwrap()
+            .history()
+            .is_empty());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics_snapshot() {
+        let hsm = create_test_hsm();
+        hsm.dispatch_event(ExampleEvents::A).unwrap();
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+
+        let snapshot = hsm.metrics_snapshot();
+        assert_eq!(snapshot.hsm_name, "TestHsm");
+        assert_eq!(snapshot.dispatches.get("A"), Some(&1));
+        assert_eq!(snapshot.dispatches.get("F"), Some(&1));
+        assert_eq!(snapshot.handled_by_state.get("LevelA1"), Some(&1));
+        assert_eq!(snapshot.handled_by_state.get("LevelA2"), Some(&1));
+        assert_eq!(
+            snapshot
+                .transitions
+                .get(&("LevelA2".to_string(), "LevelB1".to_string())),
+            Some(&1)
+        );
+        assert_eq!(snapshot.time_in_state.get("LevelA2").unwrap().count, 1);
+        assert!(snapshot
+            .to_prometheus()
+            .contains("rust_hsm_dispatches_total{hsm=\"TestHsm\",event=\"F\"} 1"));
+    }
+
+    #[test]
+    fn state_residency() {
+        let clock = Rc::new(ManualClock::new());
+        let mut builder = create_test_builder();
+        builder.set_clock(clock.clone());
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+        let init_time = clock.now();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(hsm.time_in_current_state().unwrap(), Duration::from_secs(5));
+
+        // A does not change state
+        hsm.dispatch_event(ExampleEvents::A).unwrap();
+        assert_eq!(hsm.time_in_current_state().unwrap(), Duration::from_secs(5));
+
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(hsm.time_in_current_state().unwrap(), Duration::from_secs(2));
+
+        assert_eq!(
+            hsm.state_residency(ExampleStates::LevelA2).unwrap(),
+            StateResidency {
+                entry_count: 1,
+                total_time: Duration::from_secs(5),
+                last_entered: Some(init_time),
+            }
+        );
+        let b1_residency = hsm.state_residency(ExampleStates::LevelB1).unwrap();
+        assert_eq!(b1_residency.entry_count, 1);
+        assert_eq!(b1_residency.total_time, Duration::from_secs(2));
+        // Only the current state accrues time
+        assert_eq!(
+            hsm.state_residency(ExampleStates::Top).unwrap(),
+            Default::default()
+        );
+
+        hsm.reset_residency();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(
+            hsm.state_residency(ExampleStates::LevelA2).unwrap(),
+            Default::default()
+        );
+        assert_eq!(
+            hsm.state_residency(ExampleStates::LevelB1)
+                .unwrap()
+                .total_time,
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn hierarchy_queries() {
+        let hsm = create_test_hsm();
+        assert!(hsm.is_in_state(ExampleStates::LevelA2));
+        assert!(hsm.is_in_state(ExampleStates::LevelA1));
+        assert!(hsm.is_in_state(ExampleStates::Top));
+        assert!(!hsm.is_in_state(ExampleStates::LevelB1));
+        assert_eq!(
+            hsm.active_configuration().unwrap(),
+            vec![
+                ExampleStates::Top,
+                ExampleStates::LevelA1,
+                ExampleStates::LevelA2
+            ]
+        );
+
+        assert_eq!(hsm.parent_of(ExampleStates::Top).unwrap(), None);
+        assert_eq!(
+            hsm.parent_of(ExampleStates::LevelA2).unwrap(),
+            Some(ExampleStates::LevelA1)
+        );
+        assert_eq!(
+            hsm.children_of(ExampleStates::Top).unwrap(),
+            vec![ExampleStates::LevelA1, ExampleStates::LevelB1]
+        );
+        assert!(hsm.children_of(ExampleStates::LevelB1).unwrap().is_empty());
+        assert_eq!(hsm.all_states().len(), 4);
+        assert_eq!(hsm.depth_of(ExampleStates::Top).unwrap(), 0);
+        assert_eq!(hsm.depth_of(ExampleStates::LevelA2).unwrap(), 2);
+
+        assert_eq!(
+            hsm.lca(ExampleStates::LevelA2, ExampleStates::LevelB1)
+                .unwrap(),
+            ExampleStates::Top
+        );
+        assert_eq!(
+            hsm.lca(ExampleStates::LevelA2, ExampleStates::LevelA1)
+                .unwrap(),
+            ExampleStates::LevelA1
+        );
+        assert_eq!(
+            hsm.lca(ExampleStates::LevelB1, ExampleStates::LevelB1)
+                .unwrap(),
+            ExampleStates::LevelB1
+        );
+
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        assert!(hsm.is_in_state(ExampleStates::LevelB1));
+        assert!(!hsm.is_in_state(ExampleStates::LevelA1));
+    }
+
+    /// (current state, in progress event, is_in(Top), is_in(LevelB1), pending event count)
+    type DelegateView = (ExampleStates, Option<String>, bool, bool, usize);
+
+    /// Records what the delegate reports while handling events
+    struct IntrospectingState {
+        delegate: WeakDelegate<ExampleStates, ExampleEvents>,
+        seen: Rc<RefCell<Vec<DelegateView>>>,
+    }
+
+    impl StateIF<ExampleStates, ExampleEvents> for IntrospectingState {
+        fn handle_event(&self, event: &ExampleEvents) -> bool {
+            let delegate = self.delegate.upgrade().unwrap();
+            if let ExampleEvents::A = event {
+                delegate.internal_handle_event(ExampleEvents::C).unwrap();
+            }
+            self.seen.borrow_mut().push((
+                delegate.current_state().unwrap(),
+                delegate.in_progress_event_name(),
+                delegate.is_in(ExampleStates::Top),
+                delegate.is_in(ExampleStates::LevelB1),
+                delegate.pending_event_count(),
+            ));
+            true
+        }
+    }
+
+    #[test]
+    fn delegate_queries() {
+        let mut builder = HsmBuilder::<ExampleStates, ExampleEvents>::new(
+            "IntrospectingHsm".to_string(),
+            LevelFilter::Info,
+        );
+        let seen = Rc::new(RefCell::new(vec![]));
+        for (state, parent) in [
+            (ExampleStates::Top, None),
+            (ExampleStates::LevelA1, Some(ExampleStates::Top)),
+        ] {
+            let introspecting_state = Box::new(IntrospectingState {
+                delegate: builder.get_delegate(),
+                seen: seen.clone(),
+            });
+            builder.add_state(introspecting_state, state, parent);
+        }
+
+        // Nothing to report until built
+        let delegate = builder.get_delegate().upgrade().unwrap();
+        assert!(delegate.current_state().is_err());
+        assert!(!delegate.is_in(ExampleStates::Top));
+
+        let hsm = builder.build(ExampleStates::LevelA1).unwrap();
+        hsm.dispatch_event(ExampleEvents::A).unwrap();
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                (
+                    ExampleStates::LevelA1,
+                    Some("A".to_string()),
+                    true,
+                    false,
+                    1
+                ),
+                (
+                    ExampleStates::LevelA1,
+                    Some("C".to_string()),
+                    true,
+                    false,
+                    0
+                ),
+            ]
+        );
+        assert_eq!(delegate.in_progress_event_name(), None);
+        assert_eq!(delegate.pending_event_count(), 0);
+    }
+
+    #[test]
+    fn chrome_trace() {
+        let clock = Rc::new(ManualClock::new());
+        let mut builder = create_test_builder();
+        builder.set_clock(clock.clone());
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+        assert!(hsm.stop_chrome_trace().is_none());
+
+        hsm.start_chrome_trace();
+        clock.advance(Duration::from_micros(10));
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        let trace = hsm.stop_chrome_trace().unwrap();
+
+        assert_eq!(trace.hsm_name, "TestHsm");
+        let spans: Vec<(&str, &str)> = trace
+            .events
+            .iter()
+            .map(|event| (event.category, event.name.as_str()))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("dispatch", "F"),
+                ("event", "F"),
+                ("handle_event", "LevelA2"),
+                ("exit", "LevelA2"),
+                ("exit", "LevelA1"),
+                ("enter", "LevelB1"),
+                ("start", "LevelB1"),
+            ]
+        );
+        assert!(trace
+            .events
+            .iter()
+            .all(|event| event.start == Duration::from_micros(10)));
+        assert!(trace
+            .to_json()
+            .contains("\"name\":\"LevelA2\",\"cat\":\"handle_event\",\"ph\":\"X\",\"ts\":10.000"));
+
+        // Nothing is recorded once stopped
+        hsm.dispatch_event(ExampleEvents::A).unwrap();
+        assert!(hsm.stop_chrome_trace().is_none());
+    }
+
+    #[test]
+    fn unregistered_states_rejected() {
+        assert!(matches!(
+            create_test_builder_without_b1().build(ExampleStates::LevelB1),
+            Err(HSMError::InvalidStateId(ExampleStates::LevelB1, _, _))
+        ));
+        let hsm = create_test_builder_without_b1()
+            .build(ExampleStates::LevelA2)
+            .unwrap();
+
+        let delegate = hsm.get_delegate().upgrade().unwrap();
+        assert!(delegate.change_state(ExampleStates::LevelB1).is_err());
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA2);
+        // Rejected requests do not block the next one.
+        // A2 fails to change to B1 on F, so A1 handles it instead.
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA2);
+        delegate.change_state(ExampleStates::LevelA1).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA1);
+
+        assert!(hsm.state_residency(ExampleStates::LevelB1).is_err());
+        assert!(hsm.parent_of(ExampleStates::LevelB1).is_err());
+        assert!(hsm.children_of(ExampleStates::LevelB1).is_err());
+        assert!(hsm
+            .lca(ExampleStates::LevelB1, ExampleStates::LevelA2)
+            .is_err());
+        assert!(!hsm.all_states().contains(&ExampleStates::LevelB1));
+    }
+
+    #[test]
+    fn change_state_to_current_initial_child() {
+        let mut builder = create_test_builder();
+        builder.set_initial_child(ExampleStates::LevelA1, ExampleStates::LevelA2);
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+
+        // A1 hands over to A2, which we are already in
+        let delegate = hsm.get_delegate().upgrade().unwrap();
+        delegate.change_state(ExampleStates::LevelA1).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA2);
+        delegate.change_state(ExampleStates::LevelB1).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelB1);
+    }
+
+    #[test]
+    fn labelled_transitions_are_not_enforced() {
+        let mut builder = create_test_builder();
+        builder.add_transition_event(ExampleStates::LevelA2, "F", ExampleStates::LevelB1);
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+        assert_eq!(
+            hsm.transition_registry()
+                .allowed_targets(ExampleStates::LevelA2),
+            None
+        );
+
+        // A2 only labelled its change to B1, so it may still go anywhere
+        let delegate = hsm.get_delegate().upgrade().unwrap();
+        delegate.change_state(ExampleStates::Top).unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::Top);
+    }
+
+    #[test]
+    fn illegal_transitions() {
+        let observer = Rc::new(RecordingObserver::default());
+        let mut builder = create_test_builder_with_transitions(
+            |delegate| B1Impl::new(delegate),
+            vec![],
+            vec![ExampleStates::LevelA1],
+        );
+        builder.add_observer(observer.clone());
+        let hsm = builder.build(ExampleStates::LevelA2).unwrap();
+        assert_eq!(
+            hsm.transition_registry().edges(),
+            vec![(ExampleStates::LevelA2, ExampleStates::LevelA1)]
+        );
+        observer.calls.take();
+
+        // A2 handles F by changing to B1
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelA2);
+        assert!(observer.calls.take().contains(
+            &"error State LevelA2 is not allowed to change state to LevelB1".to_string()
+        ));
+
+        let delegate = hsm.get_delegate().upgrade().unwrap();
+        delegate.change_state(ExampleStates::LevelA1).unwrap();
+        // A1 never declared its transitions
+        delegate.change_state(ExampleStates::LevelB1).unwrap();
+        assert!(matches!(
+            delegate.change_state(ExampleStates::Top),
+            Err(HSMError::IllegalTransition(
+                ExampleStates::LevelB1,
+                ExampleStates::Top,
+                _
+            ))
+        ));
+    }
+
+    /// Asks to change to its target once started, keeping the result
+    struct ChangingOnStart {
+        delegate: WeakDelegate<ExampleStates, ExampleEvents>,
+        target: ExampleStates,
+        result: Rc<RefCell<Option<HSMResult<(), ExampleStates>>>>,
+    }
+
+    impl StateIF<ExampleStates, ExampleEvents> for ChangingOnStart {
+        fn handle_event(&self, _event: &ExampleEvents) -> bool {
+            false
+        }
+
+        fn handle_state_start(&self) {
+            let result = self.delegate.upgrade().unwrap().change_state(self.target);
+            *self.result.borrow_mut() = Some(result);
+        }
+    }
+
+    #[test]
+    fn transitions_requested_by_start() {
+        let result = Rc::new(RefCell::new(None));
+        let b1_result = result.clone();
+        let hsm = create_test_builder_with_transitions(
+            move |delegate| {
+                Box::new(ChangingOnStart {
+                    delegate,
+                    target: ExampleStates::Top,
+                    result: b1_result,
+                })
+            },
+            vec![],
+            vec![ExampleStates::LevelB1, ExampleStates::Top],
+        )
+        .build(ExampleStates::LevelA2)
+        .unwrap();
+
+        // B1 asks for Top while being started by A2's change of state. Only A2 may go there.
+        hsm.dispatch_event(ExampleEvents::F(ExampleFData::default()))
+            .unwrap();
+        assert!(matches!(
+            result.take(),
+            Some(Err(HSMError::IllegalTransition(
+                ExampleStates::LevelB1,
+                ExampleStates::Top,
+                _
+            )))
+        ));
+        assert_eq!(hsm.get_current_state().unwrap(), ExampleStates::LevelB1);
+    }
+
     #[test]
     fn handle_state_change() {
         // todo!()
     }
 
+    #[allow(dead_code)]
     fn internal_handle_event() {
         // todo!()
     }
@@ -7,12 +7,27 @@ use std::rc::{Rc, Weak};
 /// Allows states to know about the HSM while the HSM knows about the states (indirectly through their trait).
 pub trait EngineDelegateIF<StateT, EventT: StateEventConstraint> {
     /// Command the HSM to change state while handling your event.
-    fn change_state(&self, new_state: u16) -> HSMResult<(), StateT>;
+    fn change_state(&self, new_state: StateT) -> HSMResult<(), StateT>;
 
     /// Command the HSM to handle an event.
     /// If this is called while handling another event, it will be queued until the current completes.
     /// If many requests are queued by states, they will be handled FIFO.
     fn internal_handle_event(&self, event: EventT) -> HSMResult<(), StateT>;
+
+    // Read-only queries. Safe to call from any state callback (handle_event, enter/exit/start).
+
+    /// The current (leaf) state.
+    /// While exiting/entering states during a change of state, this is still the state being left.
+    fn current_state(&self) -> HSMResult<StateT, StateT>;
+
+    /// Name of the event being handled (None outside of handling an event)
+    fn in_progress_event_name(&self) -> Option<String>;
+
+    /// True for the current state and all of its ancestors (UML's In() guard)
+    fn is_in(&self, state: StateT) -> bool;
+
+    /// Events queued via internal_handle_event that have not been handled yet
+    fn pending_event_count(&self) -> usize;
 }
 
 // Do not leak around the ability to share a delegate! Could lead to cycles!
@@ -42,7 +57,7 @@ pub mod delegate_test_utils {
     use std::{cell::RefCell, default::Default, marker::PhantomData};
 
     use super::*;
-    use crate::events::StateEventConstraint;
+    use crate::{errors::HSMError, events::StateEventConstraint};
 
     #[derive(Debug, strum::Display, PartialEq, Clone)]
     pub enum DelegateTestEvent {
@@ -52,8 +67,13 @@ pub mod delegate_test_utils {
 
     /// Mocked delegate that can be used to test states separate from the engine.
     pub struct MockedDelegate<StateT, EventT: StateEventConstraint> {
-        pub change_states_requested: RefCell<Vec<u16>>,
+        pub change_states_requested: RefCell<Vec<StateT>>,
         pub internal_events_handled: RefCell<Vec<EventT>>,
+        /// What the read-only queries report
+        pub current_state: RefCell<Option<StateT>>,
+        pub in_progress_event_name: RefCell<Option<String>>,
+        /// The states is_in reports as active
+        pub active_states: RefCell<Vec<StateT>>,
         marker: PhantomData<StateT>,
     }
 
@@ -68,15 +88,18 @@ pub mod delegate_test_utils {
             Self {
                 change_states_requested: RefCell::new(vec![]),
                 internal_events_handled: RefCell::new(vec![]),
+                current_state: RefCell::new(None),
+                in_progress_event_name: RefCell::new(None),
+                active_states: RefCell::new(vec![]),
                 marker: PhantomData,
             }
         }
     }
 
-    impl<StateT, EventT: StateEventConstraint> EngineDelegateIF<StateT, EventT>
+    impl<StateT: Copy + Eq, EventT: StateEventConstraint> EngineDelegateIF<StateT, EventT>
         for MockedDelegate<StateT, EventT>
     {
-        fn change_state(&self, new_state: u16) -> HSMResult<(), StateT> {
+        fn change_state(&self, new_state: StateT) -> HSMResult<(), StateT> {
             self.change_states_requested.borrow_mut().push(new_state);
             Ok(())
         }
@@ -85,14 +108,29 @@ pub mod delegate_test_utils {
             self.internal_events_handled.borrow_mut().push(event);
             Ok(())
         }
+
+        fn current_state(&self) -> HSMResult<StateT, StateT> {
+            self.current_state
+                .borrow()
+                .ok_or_else(|| HSMError::EngineNotInitialized())
+        }
+
+        fn in_progress_event_name(&self) -> Option<String> {
+            self.in_progress_event_name.borrow().clone()
+        }
+
+        fn is_in(&self, state: StateT) -> bool {
+            self.active_states.borrow().contains(&state)
+        }
+
+        fn pending_event_count(&self) -> usize {
+            self.internal_events_handled.borrow().len()
+        }
     }
 
+    #[allow(dead_code)]
     fn create_mock_delegate<StateT, EventT: StateEventConstraint>() -> MockedDelegate<StateT, EventT>
     {
-        MockedDelegate {
-            change_states_requested: Default::default(),
-            internal_events_handled: Default::default(),
-            marker: PhantomData,
-        }
+        MockedDelegate::new()
     }
 }
@@ -2,47 +2,116 @@
 //! Using this info, the entire "tree" of states can be resolved!
 
 use log::{self, LevelFilter};
-use std::{cell::Cell, collections::HashMap};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
 
 use crate::{
     errors::{HSMError, HSMResult},
     events::StateEventConstraint,
     logger::HSMLogger,
-    state::{StateBox, StateConstraint, StateContainer, StateId},
-    utils::{get_function_name, resolve_state_name},
+    state::{HistoryMode, StateBox, StateConstraint, StateContainer},
+    topology_validation::validate_topology,
+    transition_registry::TransitionRegistry,
+    utils::get_function_name,
 };
 
 /// # Brief
 /// It protects / hides the dyn states from us.
-/// In exchange, it allows us access API's when we provide tokens (StateId).
+/// In exchange, it allows us access API's when we provide tokens (StateT).
 /// Similarly, when it reports info back to us, it does so with tokens.
 pub(crate) struct StateMapping<StateT: StateConstraint, EventT: StateEventConstraint> {
-    top_state_id: Cell<Option<StateId>>,
+    top_state_id: Cell<Option<StateT>>,
     // state id -> state
-    state_map: HashMap<StateId, StateContainer<StateT, EventT>>,
+    state_map: HashMap<StateT, StateContainer<StateT, EventT>>,
     /// stateid -> parent state
     /// If the node has a parent, it is in the map!
     /// If it is not present....it is an orphan (Top)
-    state_parent_map: HashMap<StateId, StateId>,
+    state_parent_map: HashMap<StateT, StateT>,
+    /// Every state, in the order they were added
+    state_order: Vec<StateT>,
+    /// state id -> distance from the top state
+    depths: HashMap<StateT, usize>,
+    /// Child entered in place of a composite state when it is the target of a change
+    initial_children: HashMap<StateT, StateT>,
+    history_modes: HashMap<StateT, HistoryMode>,
+    /// composite state -> child active when it was last exited (through any of its descendants)
+    last_active_children: RefCell<HashMap<StateT, StateT>>,
+    /// The state requested when the HSM was built
+    initial_state: Option<StateT>,
+    transitions: TransitionRegistry<StateT>,
     logger: HSMLogger,
 }
 
 impl<StateT: StateConstraint, EventT: StateEventConstraint> StateMapping<StateT, EventT> {
     #[cfg(test)]
     pub(crate) fn new(
-        top_state_id: StateId,
-        state_map: HashMap<StateId, StateContainer<StateT, EventT>>,
-        raw_state_parent_map: HashMap<StateId, StateId>,
+        top_state_id: StateT,
+        state_map: HashMap<StateT, StateContainer<StateT, EventT>>,
+        raw_state_parent_map: HashMap<StateT, StateT>,
         logger: Option<HSMLogger>,
     ) -> Self {
-        state_map.keys().len();
-
-        Self {
+        let mut state_mapping = Self {
             top_state_id: Cell::new(Some(top_state_id)),
+            state_order: state_map.keys().cloned().collect(),
+            depths: HashMap::new(),
+            initial_children: HashMap::new(),
+            history_modes: HashMap::new(),
+            last_active_children: RefCell::new(HashMap::new()),
+            initial_state: None,
+            transitions: Default::default(),
             state_map,
             state_parent_map: raw_state_parent_map,
             logger: logger.unwrap_or(HSMLogger::from(LevelFilter::Info)),
+        };
+        state_mapping.resolve_depths();
+        state_mapping
+    }
+
+    /// # Brief
+    /// Assemble and validate the topology of an HSM from its states and their parents.
+    /// declared_depths are checked against the depths the parents resolve to (see StateHierarchy::DEPTHS).
+    /// # Errors
+    /// InvalidTopology with every issue found (see validate_topology)
+    pub(crate) fn from_states(
+        states: Vec<(StateT, Option<StateT>, StateBox<StateT, EventT>)>,
+        initial_children: Vec<(StateT, StateT)>,
+        history: Vec<(StateT, HistoryMode)>,
+        transitions: TransitionRegistry<StateT>,
+        declared_depths: &[(StateT, usize)],
+        logger: HSMLogger,
+    ) -> HSMResult<Self, StateT> {
+        let links: Vec<(StateT, Option<StateT>)> = states
+            .iter()
+            .map(|(state_id, parent_state, _)| (*state_id, *parent_state))
+            .collect();
+        let history_states: Vec<StateT> = history.iter().map(|(state, _)| *state).collect();
+        let issues = validate_topology(
+            &links,
+            &initial_children,
+            &history_states,
+            &transitions.edges(),
+            declared_depths,
+        );
+        if !issues.is_empty() {
+            for issue in &issues {
+                logger.log_error(get_function_name!(), issue.to_string().as_str());
+            }
+            return Err(HSMError::InvalidTopology(issues));
         }
+
+        let mut state_mapping = Self::new_default();
+        state_mapping.logger = logger;
+        for (state_id, parent_state, state) in states {
+            state_mapping.transfer_state(state, state_id);
+            state_mapping.add_state_internal(state_id, parent_state);
+        }
+        state_mapping.initial_children = initial_children.into_iter().collect();
+        state_mapping.history_modes = history.into_iter().collect();
+        state_mapping.transitions = transitions;
+        state_mapping.resolve_depths();
+        Ok(state_mapping)
     }
 
     pub(crate) fn new_default() -> Self {
@@ -50,53 +119,31 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> StateMapping<StateT,
             top_state_id: Cell::new(None),
             state_map: HashMap::new(),
             state_parent_map: HashMap::new(),
+            state_order: vec![],
+            depths: HashMap::new(),
+            initial_children: HashMap::new(),
+            history_modes: HashMap::new(),
+            last_active_children: RefCell::new(HashMap::new()),
+            initial_state: None,
+            transitions: Default::default(),
             logger: HSMLogger::from(LevelFilter::Info),
         }
     }
 
-    pub(crate) fn transfer_state(
-        &mut self,
-        new_state: StateBox<StateT, EventT>,
-        new_state_id: StateId,
-    ) -> HSMResult<(), StateT> {
-        let new_state_container: StateContainer<StateT, EventT> =
-            StateContainer::new(new_state_id, new_state);
-
-        // Validate the state has not been added already!
-        match self.state_map.insert(new_state_id, new_state_container) {
-            None => Ok(()),
-            Some(_) => Err(HSMError::AddDuplicateStateId(
-                StateT::from(*new_state_id.get_id()),
-                *new_state_id.get_id(),
-            )),
-        }?;
-
-        Ok(())
+    /// Only called on validated topologies
+    fn transfer_state(&mut self, new_state: StateBox<StateT, EventT>, new_state_id: StateT) {
+        self.state_map
+            .insert(new_state_id, StateContainer::new(new_state_id, new_state));
+        self.state_order.push(new_state_id);
     }
 
-    pub(crate) fn add_state_internal<T: std::fmt::Display + Into<u16> + From<u16>>(
-        &mut self,
-        new_state_id: StateId,
-        parent_state: Option<T>,
-    ) -> HSMResult<(), StateT> {
-        let new_state_name = resolve_state_name::<StateT>(&new_state_id);
-        if let Some(chosen_top) = self.top_state_id.get() {
-            if new_state_id != chosen_top && parent_state.is_none() {
-                return Err(HSMError::MultipleTopState(
-                    resolve_state_name::<StateT>(&chosen_top),
-                    *chosen_top.get_id(),
-                    new_state_name,
-                    *new_state_id.get_id(),
-                ));
+    /// Only called on validated topologies
+    fn add_state_internal(&mut self, new_state_id: StateT, parent_state: Option<StateT>) {
+        match parent_state {
+            Some(parent_state_id) => {
+                self.state_parent_map.insert(new_state_id, parent_state_id);
             }
-        };
-
-        let mut parent_state_id: Option<StateId> = None;
-        if parent_state.is_some() {
-            let parent_state_metadata = parent_state.unwrap();
-            parent_state_id = Some(StateId::new(parent_state_metadata.into()));
-            self.state_parent_map
-                .insert(new_state_id, parent_state_id.unwrap());
+            None => self.top_state_id.set(Some(new_state_id)),
         }
 
         self.logger.log_debug(
@@ -104,42 +151,130 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> StateMapping<StateT,
             format!(
                 "{}) Adding state {} with parent {}",
                 LevelFilter::Info.as_str(),
-                new_state_name,
-                match parent_state_id {
+                new_state_id,
+                match parent_state {
                     None => "None".to_owned(),
-                    Some(parent_id) => resolve_state_name::<StateT>(&parent_id),
+                    Some(parent_id) => parent_id.to_string(),
                 },
             )
             .as_str(),
         );
-        Ok(())
+    }
+
+    /// Only called on validated topologies, once every state was added
+    fn resolve_depths(&mut self) {
+        self.depths = self
+            .state_order
+            .iter()
+            .map(|state_id| {
+                let depth = std::iter::successors(self.get_parent_state_id(state_id), |parent| {
+                    self.get_parent_state_id(parent)
+                })
+                .count();
+                (*state_id, depth)
+            })
+            .collect();
+    }
+
+    /// Distance of the state from the top state
+    pub(crate) fn get_depth(&self, id: &StateT) -> HSMResult<usize, StateT> {
+        self.depths
+            .get(id)
+            .copied()
+            .ok_or_else(|| HSMError::InvalidStateId(*id, get_function_name!(), vec![]))
+    }
+
+    /// Every state with its parent, in the order they were added
+    #[cfg(test)]
+    pub(crate) fn parent_links(&self) -> Vec<(StateT, Option<StateT>)> {
+        self.state_order
+            .iter()
+            .map(|state_id| (*state_id, self.get_parent_state_id(state_id)))
+            .collect()
+    }
+
+    /// Follow the initial children (or remembered children, for states with history)
+    /// down from the state. The state itself if it has neither.
+    pub(crate) fn resolve_initial_state(&self, state_id: StateT) -> StateT {
+        let last_active_children = self.last_active_children.borrow();
+        let mut resolved_state = state_id;
+        // Deep history applies to every descendant too
+        let mut in_deep_history = false;
+        loop {
+            let history_mode = self.history_modes.get(&resolved_state);
+            in_deep_history |= history_mode == Some(&HistoryMode::Deep);
+            let remembered_child = match in_deep_history || history_mode.is_some() {
+                true => last_active_children.get(&resolved_state),
+                false => None,
+            };
+            match remembered_child.or(self.initial_children.get(&resolved_state)) {
+                None => break resolved_state,
+                Some(child) => resolved_state = *child,
+            }
+        }
+    }
+
+    /// Remember the exited state as its parent's last active child (for history)
+    pub(crate) fn record_exit(&self, state_id: StateT) {
+        if let Some(parent_state_id) = self.get_parent_state_id(&state_id) {
+            self.last_active_children
+                .borrow_mut()
+                .insert(parent_state_id, state_id);
+        }
+    }
+
+    pub(crate) fn set_initial_state(&mut self, state_id: StateT) {
+        self.initial_state = Some(state_id);
+    }
+
+    pub(crate) fn get_initial_state(&self) -> Option<StateT> {
+        self.initial_state
+    }
+
+    pub(crate) fn get_initial_child(&self, state_id: &StateT) -> Option<StateT> {
+        self.initial_children.get(state_id).copied()
+    }
+
+    pub(crate) fn get_history_mode(&self, state_id: &StateT) -> Option<HistoryMode> {
+        self.history_modes.get(state_id).copied()
+    }
+
+    pub(crate) fn get_transitions(&self) -> &TransitionRegistry<StateT> {
+        &self.transitions
     }
 
     /// Return the id of state's parent
-    pub(crate) fn get_parent_state_id(&self, id: &StateId) -> Option<StateId> {
+    pub(crate) fn get_parent_state_id(&self, id: &StateT) -> Option<StateT> {
         self.state_parent_map.get(id).cloned()
     }
 
-    pub(crate) fn is_state_valid(&self, id: &StateId) -> bool {
+    /// The state's direct children (in the order they were added)
+    pub(crate) fn get_children_state_ids(&self, id: &StateT) -> Vec<StateT> {
+        self.get_all_state_ids()
+            .into_iter()
+            .filter(|state_id| self.state_parent_map.get(state_id) == Some(id))
+            .collect()
+    }
+
+    /// Every state (in the order they were added)
+    pub(crate) fn get_all_state_ids(&self) -> Vec<StateT> {
+        self.state_order.clone()
+    }
+
+    pub(crate) fn is_state_valid(&self, id: &StateT) -> bool {
         self.state_map.contains_key(id)
     }
 
-    pub(crate) fn handle_event(&self, id: &StateId, event: &EventT) -> HSMResult<bool, StateT> {
+    pub(crate) fn handle_event(&self, id: &StateT, event: &EventT) -> HSMResult<bool, StateT> {
         match self.state_map.get(id) {
-            None => Err(HSMError::InvalidStateId(
-                StateT::from(*id.get_id()),
-                get_function_name!(),
-            )),
+            None => Err(HSMError::InvalidStateId(*id, get_function_name!(), vec![])),
             Some(container) => Ok(container.state_ref.handle_event(event)),
         }
     }
 
-    pub(crate) fn handle_state_enter(&self, id: &StateId) -> HSMResult<(), StateT> {
+    pub(crate) fn handle_state_enter(&self, id: &StateT) -> HSMResult<(), StateT> {
         match self.state_map.get(id) {
-            None => Err(HSMError::InvalidStateId(
-                StateT::from(*id.get_id()),
-                get_function_name!(),
-            )),
+            None => Err(HSMError::InvalidStateId(*id, get_function_name!(), vec![])),
             Some(container) => {
                 container.state_ref.handle_state_enter();
                 Ok(())
@@ -147,12 +282,9 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> StateMapping<StateT,
         }
     }
 
-    pub(crate) fn handle_state_start(&self, id: &StateId) -> HSMResult<(), StateT> {
+    pub(crate) fn handle_state_start(&self, id: &StateT) -> HSMResult<(), StateT> {
         match self.state_map.get(id) {
-            None => Err(HSMError::InvalidStateId(
-                StateT::from(*id.get_id()),
-                get_function_name!(),
-            )),
+            None => Err(HSMError::InvalidStateId(*id, get_function_name!(), vec![])),
             Some(container) => {
                 container.state_ref.handle_state_start();
                 Ok(())
@@ -160,12 +292,9 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> StateMapping<StateT,
         }
     }
 
-    pub(crate) fn handle_state_exit(&self, id: &StateId) -> HSMResult<(), StateT> {
+    pub(crate) fn handle_state_exit(&self, id: &StateT) -> HSMResult<(), StateT> {
         match self.state_map.get(id) {
-            None => Err(HSMError::InvalidStateId(
-                StateT::from(*id.get_id()),
-                get_function_name!(),
-            )),
+            None => Err(HSMError::InvalidStateId(*id, get_function_name!(), vec![])),
             Some(container) => {
                 container.state_ref.handle_state_exit();
                 Ok(())
@@ -176,58 +305,51 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> StateMapping<StateT,
     /// get LCA between current state and other state
     pub(crate) fn find_lca(
         &self,
-        source_state: &StateId,
-        target_state: &StateId,
-    ) -> HSMResult<StateId, StateT> {
+        source_state: &StateT,
+        target_state: &StateT,
+    ) -> HSMResult<StateT, StateT> {
         assert!(source_state != target_state);
-        //  USE resolve_path_to_root from state mapping
-        let source_path_to_root = self.resolve_path_to_root(source_state)?;
-        let target_path_to_root = self.resolve_path_to_root(target_state)?;
-
-        let mut root_to_source_path = source_path_to_root;
-        root_to_source_path.reverse();
-        let mut root_to_target_path = target_path_to_root;
-        root_to_target_path.reverse();
-
-        // Compare the two paths, starting from the ends of the paths (where the root is)
-        // keep going until the nodes diverge. The last node before the paths diverge is the LCA.
-        // this works but is gross
-
-        // https://stackoverflow.com/a/29504547/14810215
-        // Get all differences between them...but we only care about the first
-        let shared_paths = root_to_source_path
-            .iter()
-            .zip(root_to_target_path.iter())
-            .filter(|&(source_node, target_node)| source_node == target_node)
-            .collect::<Vec<(&StateId, &StateId)>>();
-        if shared_paths.is_empty() {
-            return Err(HSMError::LCAOfSameNode());
+        // Only states of different trees have no parent left to compare
+        let parent = |state: StateT| {
+            self.get_parent_state_id(&state)
+                .ok_or(HSMError::LCAOfSameNode())
+        };
+        let (mut source, mut source_depth) = (*source_state, self.get_depth(source_state)?);
+        let (mut target, mut target_depth) = (*target_state, self.get_depth(target_state)?);
+        // Bring both states to the same depth, then walk up together until they meet
+        while source_depth > target_depth {
+            source = parent(source)?;
+            source_depth -= 1;
         }
-        let last_known_common_state = *shared_paths.iter().last().unwrap().0;
-
-        Ok(last_known_common_state)
+        while target_depth > source_depth {
+            target = parent(target)?;
+            target_depth -= 1;
+        }
+        while source != target {
+            source = parent(source)?;
+            target = parent(target)?;
+        }
+        Ok(source)
     }
 
     /// Path to root node from provided node (inclusive on both ends!)
     /// [start, ..., end]
     pub(crate) fn resolve_path_to_root(
         &self,
-        start_node: &StateId,
-    ) -> HSMResult<Vec<StateId>, StateT> {
-        let mut current_node_id: StateId = *start_node;
+        start_node: &StateT,
+    ) -> HSMResult<Vec<StateT>, StateT> {
+        let mut current_node_id: StateT = *start_node;
         let current_node = self
             .state_map
             .get(start_node)
-            .ok_or_else(|| {
-                HSMError::InvalidStateId(StateT::from(*start_node.get_id()), get_function_name!())
-            })?
+            .ok_or_else(|| HSMError::InvalidStateId(*start_node, get_function_name!(), vec![]))?
             .state_id;
-        let mut path_to_root: Vec<StateId> = vec![current_node];
+        let mut path_to_root: Vec<StateT> = vec![current_node];
 
         loop {
             self.logger.log_debug(
                 get_function_name!(),
-                format!("Current State: {}", StateT::from(*current_node_id.get_id())).as_str(),
+                format!("Current State: {}", current_node_id).as_str(),
             );
             current_node_id = match self.get_parent_state_id(&current_node_id) {
                 None => {
@@ -238,15 +360,15 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> StateMapping<StateT,
                 Some(parent_id) => {
                     match self.is_state_id_valid(&parent_id) {
                         false => Err(HSMError::ImpossibleStateMismatch(
-                            StateT::from(*current_node_id.get_id()),
-                            StateT::from(*parent_id.get_id()),
+                            current_node_id,
+                            parent_id,
                         )),
                         true => Ok(()),
                     }?;
                     path_to_root.push(parent_id);
                     self.logger.log_debug(
                         get_function_name!(),
-                        format!("Next State: {}", StateT::from(*parent_id.get_id())).as_str(),
+                        format!("Next State: {}", parent_id).as_str(),
                     );
                     parent_id
                 }
@@ -256,42 +378,16 @@ impl<StateT: StateConstraint, EventT: StateEventConstraint> StateMapping<StateT,
         Ok(path_to_root)
     }
 
-    // Given 2 mappings of containers, validate no illegal combinations exist
-    // Pan
-    pub(crate) fn validate_cross_states(&self) -> HSMResult<(), StateT> {
-        for (parent_lookup_id, parent_id) in &self.state_parent_map {
-            if !self.state_map.contains_key(parent_lookup_id) {
-                let msg = format!(
-                    "State id from parent map {} does not exist in state map!",
-                    parent_lookup_id
-                );
-                self.logger.log_error(get_function_name!(), msg.as_str());
-                return Err(HSMError::MapValidationError(msg));
-            }
-
-            if !self.state_map.contains_key(parent_id) {
-                let msg = format!(
-                    "Parent State id from parent map {} does not exist in state map!",
-                    parent_id
-                );
-                self.logger.log_error(get_function_name!(), msg.as_str());
-                return Err(HSMError::MapValidationError(msg));
-            }
-        }
-
-        // Do we need to check if both have the same size?
-        Ok(())
-    }
-
-    pub(crate) fn is_state_id_valid(&self, state_id: &StateId) -> bool {
+    pub(crate) fn is_state_id_valid(&self, state_id: &StateT) -> bool {
         self.state_map.contains_key(state_id)
     }
-    pub(crate) fn is_state_id_valid_result(&self, state_id: &StateId) -> HSMResult<(), StateT> {
+    pub(crate) fn is_state_id_valid_result(&self, state_id: &StateT) -> HSMResult<(), StateT> {
         match self.is_state_id_valid(state_id) {
             true => Ok(()),
             false => Err(HSMError::InvalidStateId(
-                StateT::from(*state_id.get_id()),
+                *state_id,
                 get_function_name!(),
+                vec![],
             )),
         }
     }
@@ -307,20 +403,21 @@ mod tests {
     use super::*;
 
     // fn do_paths_match(a: &Vec<StateContainer<ExampleStates>>, b: &Vec<StateContainer<ExampleStates>>) -> bool {
-    fn do_paths_match(a: &Vec<StateId>, b: &Vec<StateId>) -> bool {
+    fn do_paths_match(a: &[ExampleStates], b: &[ExampleStates]) -> bool {
         let matching = a.iter().zip(b.iter()).filter(|&(a, b)| a == b).count();
         matching == a.len() && matching == b.len()
     }
 
-    fn resolve_path_to_id(path: &Vec<StateId>) -> Vec<StateId> {
-        path.iter().copied().collect()
+    fn resolve_path_to_id(path: &[ExampleStates]) -> Vec<ExampleStates> {
+        path.to_vec()
     }
 
     #[test]
     fn parent_link() {
         let test_logger = HSMLogger::new(LevelFilter::Trace);
-        let mut state_map = HashMap::<StateId, StateContainer<ExampleStates, ExampleEvents>>::new();
-        let mut raw_parent_map = HashMap::<StateId, StateId>::new();
+        let mut state_map =
+            HashMap::<ExampleStates, StateContainer<ExampleStates, ExampleEvents>>::new();
+        let mut raw_parent_map = HashMap::<ExampleStates, ExampleStates>::new();
         let mut num_states_created: u16 = 0;
 
         let top_state = DummyStateStruct::new(&mut num_states_created);
@@ -331,24 +428,21 @@ mod tests {
         assert!(num_states_created == 3);
         let a2_state = DummyStateStruct::new(&mut num_states_created);
         assert!(num_states_created == 4);
-        let top_container = StateContainer::new(StateId::new(ExampleStates::Top.into()), top_state);
-        let a1_container =
-            StateContainer::new(StateId::new(ExampleStates::LevelA1.into()), a1_state);
-        let b1_container =
-            StateContainer::new(StateId::new(ExampleStates::LevelB1.into()), b1_state);
-        let a2_container =
-            StateContainer::new(StateId::new(ExampleStates::LevelA2.into()), a2_state);
-        state_map.insert(ExampleStates::Top.into(), top_container);
-        state_map.insert(ExampleStates::LevelA1.into(), a1_container);
-        state_map.insert(ExampleStates::LevelB1.into(), b1_container);
-        state_map.insert(ExampleStates::LevelA2.into(), a2_container);
-
-        raw_parent_map.insert(ExampleStates::LevelA1.into(), ExampleStates::Top.into());
-        raw_parent_map.insert(ExampleStates::LevelB1.into(), ExampleStates::Top.into());
-        raw_parent_map.insert(ExampleStates::LevelA2.into(), ExampleStates::LevelA1.into());
+        let top_container = StateContainer::new(ExampleStates::Top, top_state);
+        let a1_container = StateContainer::new(ExampleStates::LevelA1, a1_state);
+        let b1_container = StateContainer::new(ExampleStates::LevelB1, b1_state);
+        let a2_container = StateContainer::new(ExampleStates::LevelA2, a2_state);
+        state_map.insert(ExampleStates::Top, top_container);
+        state_map.insert(ExampleStates::LevelA1, a1_container);
+        state_map.insert(ExampleStates::LevelB1, b1_container);
+        state_map.insert(ExampleStates::LevelA2, a2_container);
+
+        raw_parent_map.insert(ExampleStates::LevelA1, ExampleStates::Top);
+        raw_parent_map.insert(ExampleStates::LevelB1, ExampleStates::Top);
+        raw_parent_map.insert(ExampleStates::LevelA2, ExampleStates::LevelA1);
 
         let mapping = StateMapping::<ExampleStates, ExampleEvents>::new(
-            ExampleStates::Top.into(),
+            ExampleStates::Top,
             state_map,
             raw_parent_map,
             Some(LevelFilter::Trace.into()),
@@ -356,46 +450,42 @@ mod tests {
 
         test_logger.log_info(get_function_name!(), "Assembled the mappings!");
 
-        assert!(mapping
-            .get_parent_state_id(&ExampleStates::Top.into())
-            .is_none());
+        assert!(mapping.get_parent_state_id(&ExampleStates::Top).is_none());
         assert_eq!(
             mapping
-                .get_parent_state_id(&ExampleStates::LevelA1.into())
+                .get_parent_state_id(&ExampleStates::LevelA1)
                 .unwrap(),
-            ExampleStates::Top.into()
+            ExampleStates::Top
         );
         assert_eq!(
             mapping
-                .get_parent_state_id(&ExampleStates::LevelB1.into())
+                .get_parent_state_id(&ExampleStates::LevelB1)
                 .unwrap(),
-            ExampleStates::Top.into()
+            ExampleStates::Top
         );
         assert_eq!(
             mapping
-                .get_parent_state_id(&ExampleStates::LevelA2.into())
+                .get_parent_state_id(&ExampleStates::LevelA2)
                 .unwrap(),
-            ExampleStates::LevelA1.into()
+            ExampleStates::LevelA1
         );
 
-        assert!(!mapping.is_state_id_valid(&StateId::from(7)));
-        assert!(mapping.is_state_id_valid(&ExampleStates::Top.into()));
-        assert!(mapping.is_state_id_valid(&ExampleStates::LevelA1.into()));
-        assert!(mapping.is_state_id_valid(&ExampleStates::LevelB1.into()));
-        assert!(mapping.is_state_id_valid(&ExampleStates::LevelA2.into()));
-        assert!(!mapping.is_state_id_valid(&ExampleStates::INVALID.into()));
+        assert!(mapping.is_state_id_valid(&ExampleStates::Top));
+        assert!(mapping.is_state_id_valid(&ExampleStates::LevelA1));
+        assert!(mapping.is_state_id_valid(&ExampleStates::LevelB1));
+        assert!(mapping.is_state_id_valid(&ExampleStates::LevelA2));
 
-        assert!(mapping.validate_cross_states().is_ok());
+        assert!(validate_topology(&mapping.parent_links(), &[], &[], &[], &[]).is_empty());
         test_logger.log_info(
             get_function_name!(),
             "Passed verifying raw data structures! Onto Algorithms",
         );
 
         {
-            let path = mapping.resolve_path_to_root(&ExampleStates::Top.into());
+            let path = mapping.resolve_path_to_root(&ExampleStates::Top);
             assert!(path.is_ok());
             let id_paths = resolve_path_to_id(&path.unwrap());
-            let expected_id_path: Vec<StateId> = vec![ExampleStates::Top.into()];
+            let expected_id_path: Vec<ExampleStates> = vec![ExampleStates::Top];
             assert!(
                 do_paths_match(&id_paths, &expected_id_path),
                 "expected {:?}. Received {:?} ",
@@ -404,53 +494,57 @@ mod tests {
             );
         }
         {
-            let path = mapping.resolve_path_to_root(&ExampleStates::LevelA1.into());
+            let path = mapping.resolve_path_to_root(&ExampleStates::LevelA1);
             assert!(path.is_ok());
             let id_paths = resolve_path_to_id(&path.unwrap());
-            let expected_id_path: Vec<StateId> =
-                vec![ExampleStates::LevelA1.into(), ExampleStates::Top.into()];
+            let expected_id_path: Vec<ExampleStates> =
+                vec![ExampleStates::LevelA1, ExampleStates::Top];
             assert!(
                 do_paths_match(&id_paths, &expected_id_path),
                 "expected {:?}. Received {:?} ",
-                cast_id_vector(&expected_id_path),
-                cast_id_vector(&id_paths)
+                expected_id_path,
+                id_paths
             );
             test_logger.log_info(get_function_name!(), "A1 -> Root is good");
         }
         {
-            let path = mapping.resolve_path_to_root(&ExampleStates::LevelB1.into());
+            let path = mapping.resolve_path_to_root(&ExampleStates::LevelB1);
             assert!(path.is_ok());
             let id_paths = resolve_path_to_id(&path.unwrap());
-            let expected_id_path: Vec<StateId> =
-                vec![ExampleStates::LevelB1.into(), ExampleStates::Top.into()];
+            let expected_id_path: Vec<ExampleStates> =
+                vec![ExampleStates::LevelB1, ExampleStates::Top];
             assert!(
                 do_paths_match(&id_paths, &expected_id_path),
                 "expected {:?}. Received {:?} ",
-                cast_id_vector(&expected_id_path),
-                cast_id_vector(&id_paths)
+                expected_id_path,
+                id_paths
             );
             test_logger.log_info(get_function_name!(), "B1 -> Root is good");
         }
         {
-            let path = mapping.resolve_path_to_root(&ExampleStates::LevelA2.into());
+            let path = mapping.resolve_path_to_root(&ExampleStates::LevelA2);
             assert!(path.is_ok());
             let id_paths = resolve_path_to_id(&path.unwrap());
-            let expected_id_path: Vec<StateId> = vec![
-                ExampleStates::LevelA2.into(),
-                ExampleStates::LevelA1.into(),
-                ExampleStates::Top.into(),
+            let expected_id_path: Vec<ExampleStates> = vec![
+                ExampleStates::LevelA2,
+                ExampleStates::LevelA1,
+                ExampleStates::Top,
             ];
             assert!(
                 do_paths_match(&id_paths, &expected_id_path),
                 "expected {:?}. Received {:?} ",
-                cast_id_vector(&expected_id_path),
-                cast_id_vector(&id_paths)
+                expected_id_path,
+                id_paths
             );
             test_logger.log_info(get_function_name!(), "A2 -> Root is good");
         }
         {
-            let path = mapping.resolve_path_to_root(&ExampleStates::INVALID.into());
-            assert!(path.is_err());
+            // States never added are unknown
+            let empty_mapping = StateMapping::<ExampleStates, ExampleEvents>::new_default();
+            assert!(!empty_mapping.is_state_id_valid(&ExampleStates::Top));
+            assert!(empty_mapping
+                .resolve_path_to_root(&ExampleStates::Top)
+                .is_err());
         }
     }
 }
@@ -1,11 +1,14 @@
 //! Contains structs and data useful across the module when running tests
-///
 use crate::{
+    builder::HsmBuilder,
+    errors::HSMResult,
     examples::ExampleStates,
     examples::*,
-    state::{StateConstraint, StateIF, StateId},
+    logger::HSMLogger,
+    state::{StateBox, StateConstraint, StateIF},
     state_engine::HSM,
-    state_engine_delegate::delegate_test_utils::MockedDelegate,
+    state_engine_delegate::{delegate_test_utils::MockedDelegate, SharedDelegate, WeakDelegate},
+    use_delegate,
 };
 
 use log;
@@ -60,27 +63,185 @@ impl DummyStateStruct<ExampleStates> {
     }
 }
 
-pub(crate) fn cast_id_vector(state_list: &Vec<StateId>) -> Vec<ExampleStates> {
-    let states: Vec<ExampleStates> = state_list
-        .iter()
-        .map(|state_id| ExampleStates::from(*state_id.get_id()))
-        .collect();
-    states
+/// A2Impl, except F changes state to LevelB1 (i.e. to test transitions through dispatch)
+pub struct ChangingA2 {
+    example: Box<A2Impl>,
+}
+
+impl ChangingA2 {
+    pub fn new(delegate: WeakDelegate<ExampleStates, ExampleEvents>) -> Box<Self> {
+        Box::new(Self {
+            example: A2Impl::new(delegate),
+        })
+    }
+}
+
+impl StateIF<ExampleStates, ExampleEvents> for ChangingA2 {
+    fn handle_event(&self, event: &ExampleEvents) -> bool {
+        match event {
+            ExampleEvents::F(_) => {
+                // Release our borrow before changing state - exiting us borrows the data again!
+                let delegate = self.example.data.borrow().delegate.clone();
+                use_delegate!(
+                    delegate,
+                    |rc: SharedDelegate<ExampleStates, ExampleEvents>| rc
+                        .change_state(ExampleStates::LevelB1)
+                        .is_ok(),
+                    false
+                )
+            }
+            _ => self.example.handle_event(event),
+        }
+    }
+
+    fn handle_state_enter(&self) {
+        self.example.handle_state_enter();
+    }
+
+    fn handle_state_start(&self) {
+        self.example.handle_state_start();
+    }
+
+    fn handle_state_exit(&self) {
+        self.example.handle_state_exit();
+    }
+
+    fn change_state_during_handle(
+        &self,
+        new_state: ExampleStates,
+        delegate: SharedDelegate<ExampleStates, ExampleEvents>,
+    ) -> HSMResult<(), ExampleStates> {
+        self.example.change_state_during_handle(new_state, delegate)
+    }
+}
+
+/// B1Impl, except E fires an A back at the engine (which Top handles), i.e. to test internal events
+pub struct FiringB1 {
+    example: Box<B1Impl>,
+}
+
+impl FiringB1 {
+    pub fn new(delegate: WeakDelegate<ExampleStates, ExampleEvents>) -> Box<Self> {
+        Box::new(Self {
+            example: B1Impl::new(delegate),
+        })
+    }
+}
+
+impl StateIF<ExampleStates, ExampleEvents> for FiringB1 {
+    fn handle_event(&self, event: &ExampleEvents) -> bool {
+        match event {
+            ExampleEvents::E(_) => {
+                let delegate = self.example.data.borrow().delegate.clone();
+                use_delegate!(
+                    delegate,
+                    |rc: SharedDelegate<ExampleStates, ExampleEvents>| rc
+                        .internal_handle_event(ExampleEvents::A)
+                        .is_ok(),
+                    false
+                )
+            }
+            _ => self.example.handle_event(event),
+        }
+    }
+
+    fn handle_state_enter(&self) {
+        self.example.handle_state_enter();
+    }
+
+    fn handle_state_start(&self) {
+        self.example.handle_state_start();
+    }
+
+    fn handle_state_exit(&self) {
+        self.example.handle_state_exit();
+    }
+
+    fn change_state_during_handle(
+        &self,
+        new_state: ExampleStates,
+        delegate: SharedDelegate<ExampleStates, ExampleEvents>,
+    ) -> HSMResult<(), ExampleStates> {
+        self.example.change_state_during_handle(new_state, delegate)
+    }
 }
 
 pub fn create_test_hsm() -> HSM<ExampleStates, ExampleEvents> {
-    let hsm = HSM::new("TestHsm".to_string(), LevelFilter::Info).unwrap();
-    let top = Top::new(hsm.get_delegate());
-    let a1 = A1Impl::new(hsm.get_delegate());
-    let b1 = B1Impl::new(hsm.get_delegate());
-    let a2 = A2Impl::new(hsm.get_delegate());
-
-    hsm.add_state(top, ExampleStates::Top, None).unwrap();
-    hsm.add_state(a1, ExampleStates::LevelA1, Some(ExampleStates::Top))
-        .unwrap();
-    hsm.add_state(b1, ExampleStates::LevelB1, Some(ExampleStates::Top))
-        .unwrap();
-    hsm.add_state(a2, ExampleStates::LevelA2, Some(ExampleStates::LevelA1))
-        .unwrap();
-    hsm
+    create_test_builder().build(ExampleStates::LevelA2).unwrap()
+}
+
+pub fn create_test_hsm_with_logger(logger: HSMLogger) -> HSM<ExampleStates, ExampleEvents> {
+    create_test_builder_with_logger(logger)
+        .build(ExampleStates::LevelA2)
+        .unwrap()
+}
+
+/// All the example states (LevelA2 and LevelB1 as ChangingA2 and FiringB1) added, but not built yet (i.e. to add observers/clocks first)
+pub fn create_test_builder() -> HsmBuilder<ExampleStates, ExampleEvents> {
+    create_test_builder_with_logger(HSMLogger::new(LevelFilter::Info))
+}
+
+pub fn create_test_builder_with_logger(
+    logger: HSMLogger,
+) -> HsmBuilder<ExampleStates, ExampleEvents> {
+    let mut builder = HsmBuilder::new_with_logger("TestHsm".to_string(), logger);
+    let top = Top::new(builder.get_delegate());
+    let a1 = A1Impl::new(builder.get_delegate());
+    let b1 = FiringB1::new(builder.get_delegate());
+    let a2 = ChangingA2::new(builder.get_delegate());
+
+    builder
+        .add_state(top, ExampleStates::Top, None)
+        .add_state(a1, ExampleStates::LevelA1, Some(ExampleStates::Top))
+        .add_state(b1, ExampleStates::LevelB1, Some(ExampleStates::Top))
+        .add_state(a2, ExampleStates::LevelA2, Some(ExampleStates::LevelA1));
+    builder
+}
+
+/// Same as create_test_builder, but LevelB1 is created by create_b1,
+/// and LevelB1 and LevelA2 may only change to the targets listed for them (see add_state_with_transitions)
+pub fn create_test_builder_with_transitions<F>(
+    create_b1: F,
+    b1_targets: Vec<ExampleStates>,
+    a2_targets: Vec<ExampleStates>,
+) -> HsmBuilder<ExampleStates, ExampleEvents>
+where
+    F: FnOnce(WeakDelegate<ExampleStates, ExampleEvents>) -> StateBox<ExampleStates, ExampleEvents>,
+{
+    let mut builder = HsmBuilder::new("TestHsm".to_string(), LevelFilter::Info);
+    let top = Top::new(builder.get_delegate());
+    let a1 = A1Impl::new(builder.get_delegate());
+    let b1 = create_b1(builder.get_delegate());
+    let a2 = ChangingA2::new(builder.get_delegate());
+
+    builder
+        .add_state(top, ExampleStates::Top, None)
+        .add_state(a1, ExampleStates::LevelA1, Some(ExampleStates::Top))
+        .add_state_with_transitions(
+            b1,
+            ExampleStates::LevelB1,
+            Some(ExampleStates::Top),
+            b1_targets,
+        )
+        .add_state_with_transitions(
+            a2,
+            ExampleStates::LevelA2,
+            Some(ExampleStates::LevelA1),
+            a2_targets,
+        );
+    builder
+}
+
+/// Same as create_test_builder, but LevelB1 is never added
+pub fn create_test_builder_without_b1() -> HsmBuilder<ExampleStates, ExampleEvents> {
+    let mut builder = HsmBuilder::new("TestHsmWithoutB1".to_string(), LevelFilter::Info);
+    let top = Top::new(builder.get_delegate());
+    let a1 = A1Impl::new(builder.get_delegate());
+    let a2 = ChangingA2::new(builder.get_delegate());
+
+    builder
+        .add_state(top, ExampleStates::Top, None)
+        .add_state(a1, ExampleStates::LevelA1, Some(ExampleStates::Top))
+        .add_state(a2, ExampleStates::LevelA2, Some(ExampleStates::LevelA1));
+    builder
 }
@@ -0,0 +1,410 @@
+//! Checks the parent links of every state before a machine is assembled.
+//! All the problems are collected, so they can be fixed in one go.
+//! States are their own keys, so there is no id conversion left to check.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use crate::state::StateConstraint;
+
+/// A single problem found in the topology of an HSM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopologyIssue<StateT> {
+    /// The state was added more than once
+    DuplicateState(StateT),
+    /// No state was added without a parent
+    NoRoot,
+    /// More than one state was added without a parent (in the order added)
+    MultipleRoots(Vec<StateT>),
+    /// The state's parent was never added
+    MissingParent { state: StateT, parent: StateT },
+    /// Following the parents of these states leads back to the first one
+    Cycle(Vec<StateT>),
+    /// The state's ancestors never reach a root (i.e. they are orphaned or part of a cycle)
+    Unreachable(StateT),
+    /// The initial child is not a (direct) child of the state it was set on
+    InvalidInitialChild { parent: StateT, child: StateT },
+    /// More than one initial child was set on the state
+    MultipleInitialChildren(StateT),
+    /// History was set on a state without children (or one that was never added)
+    InvalidHistoryState(StateT),
+    /// Transitions were declared from a state that was never added
+    UnknownTransitionSource { state: StateT, target: StateT },
+    /// The state is allowed to change to a state that was never added
+    UnknownTransitionTarget { state: StateT, target: StateT },
+    /// The depth declared for the state (i.e. by `#[hsm_states]`) is not its distance from the top state
+    DepthMismatch {
+        state: StateT,
+        declared: usize,
+        actual: usize,
+    },
+}
+
+impl<StateT: Display> Display for TopologyIssue<StateT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TopologyIssue::DuplicateState(state) => {
+                write!(f, "State {} added more than once", state)
+            }
+            TopologyIssue::NoRoot => write!(f, "No top state (a state without a parent)"),
+            TopologyIssue::MultipleRoots(roots) => {
+                write!(f, "Multiple top states: {}", join_states(roots))
+            }
+            TopologyIssue::MissingParent { state, parent } => {
+                write!(
+                    f,
+                    "State {} has parent {}, which was never added",
+                    state, parent
+                )
+            }
+            TopologyIssue::Cycle(states) => write!(f, "Cycle of parents: {}", join_states(states)),
+            TopologyIssue::Unreachable(state) => {
+                write!(f, "State {} can not reach the top state", state)
+            }
+            TopologyIssue::InvalidInitialChild { parent, child } => {
+                write!(f, "Initial child {} is not a child of {}", child, parent)
+            }
+            TopologyIssue::MultipleInitialChildren(parent) => {
+                write!(f, "State {} has more than one initial child", parent)
+            }
+            TopologyIssue::InvalidHistoryState(state) => {
+                write!(f, "State {} has history, but no children", state)
+            }
+            TopologyIssue::UnknownTransitionSource { state, target } => {
+                write!(
+                    f,
+                    "State {} is allowed to change to {}, but was never added",
+                    state, target
+                )
+            }
+            TopologyIssue::UnknownTransitionTarget { state, target } => {
+                write!(
+                    f,
+                    "State {} is allowed to change to {}, which was never added",
+                    state, target
+                )
+            }
+            TopologyIssue::DepthMismatch {
+                state,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "State {} was declared at depth {}, but is at depth {}",
+                state, declared, actual
+            ),
+        }
+    }
+}
+
+fn join_states<StateT: Display>(states: &[StateT]) -> String {
+    states
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join(" -> ")
+}
+
+/// # Brief
+/// Find every problem with the given (state, parent), (parent, initial child)
+/// and (source, allowed target) links, the states given history and the declared (state, depth)s.
+/// Empty if the topology is valid. Issues are reported in the order the states were added.
+pub(crate) fn validate_topology<StateT: StateConstraint>(
+    states: &[(StateT, Option<StateT>)],
+    initial_children: &[(StateT, StateT)],
+    history_states: &[StateT],
+    transitions: &[(StateT, StateT)],
+    declared_depths: &[(StateT, usize)],
+) -> Vec<TopologyIssue<StateT>> {
+    let mut issues = vec![];
+
+    // The first addition of a state wins
+    let mut parents: HashMap<StateT, Option<StateT>> = HashMap::new();
+    let mut order: Vec<StateT> = vec![];
+    for (state, parent) in states {
+        if parents.contains_key(state) {
+            issues.push(TopologyIssue::DuplicateState(*state));
+            continue;
+        }
+        parents.insert(*state, *parent);
+        order.push(*state);
+    }
+
+    let roots: Vec<StateT> = order
+        .iter()
+        .filter(|state| parents[*state].is_none())
+        .copied()
+        .collect();
+    match roots.len() {
+        0 => issues.push(TopologyIssue::NoRoot),
+        1 => {}
+        _ => issues.push(TopologyIssue::MultipleRoots(roots)),
+    }
+
+    // States already explained by a more specific issue
+    let mut flagged: HashSet<StateT> = HashSet::new();
+    for state in &order {
+        if let Some(parent) = parents[state] {
+            if !parents.contains_key(&parent) {
+                issues.push(TopologyIssue::MissingParent {
+                    state: *state,
+                    parent,
+                });
+                flagged.insert(*state);
+            }
+        }
+    }
+
+    // Walk up from every state, remembering which states are known to reach a root
+    let mut reaches_root: HashMap<StateT, bool> = HashMap::new();
+    for state in &order {
+        let mut path: Vec<StateT> = vec![];
+        let mut current_state = *state;
+        let reached = loop {
+            if let Some(reached) = reaches_root.get(&current_state) {
+                break *reached;
+            }
+            if let Some(cycle_start) = path.iter().position(|s| *s == current_state) {
+                let cycle = path[cycle_start..].to_vec();
+                flagged.extend(cycle.iter().copied());
+                issues.push(TopologyIssue::Cycle(cycle));
+                break false;
+            }
+            match parents.get(&current_state) {
+                // Missing parent, already reported
+                None => break false,
+                Some(parent) => {
+                    path.push(current_state);
+                    match parent {
+                        None => break true,
+                        Some(parent) => current_state = *parent,
+                    }
+                }
+            }
+        };
+        for visited_state in path {
+            reaches_root.insert(visited_state, reached);
+        }
+    }
+
+    issues.extend(
+        order
+            .iter()
+            .filter(|state| !reaches_root[*state] && !flagged.contains(*state))
+            .map(|state| TopologyIssue::Unreachable(*state)),
+    );
+
+    let mut has_initial_child: HashSet<StateT> = HashSet::new();
+    for (parent, child) in initial_children {
+        if parents.get(child) != Some(&Some(*parent)) {
+            issues.push(TopologyIssue::InvalidInitialChild {
+                parent: *parent,
+                child: *child,
+            });
+        }
+        if !has_initial_child.insert(*parent) {
+            issues.push(TopologyIssue::MultipleInitialChildren(*parent));
+        }
+    }
+
+    issues.extend(
+        history_states
+            .iter()
+            .filter(|state| !parents.values().any(|parent| *parent == Some(**state)))
+            .map(|state| TopologyIssue::InvalidHistoryState(*state)),
+    );
+
+    for (state, target) in transitions {
+        if !parents.contains_key(state) {
+            issues.push(TopologyIssue::UnknownTransitionSource {
+                state: *state,
+                target: *target,
+            });
+        }
+        if !parents.contains_key(target) {
+            issues.push(TopologyIssue::UnknownTransitionTarget {
+                state: *state,
+                target: *target,
+            });
+        }
+    }
+
+    // Only states that reach the top have a depth to compare with
+    for (state, declared) in declared_depths {
+        if !reaches_root.get(state).copied().unwrap_or_default() || flagged.contains(state) {
+            continue;
+        }
+        let actual = std::iter::successors(parents[state], |parent| parents[parent]).count();
+        if actual != *declared {
+            issues.push(TopologyIssue::DepthMismatch {
+                state: *state,
+                declared: *declared,
+                actual,
+            });
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::ExampleStates::{self, *};
+
+    #[test]
+    fn valid_topology() {
+        let states: Vec<(ExampleStates, Option<ExampleStates>)> = vec![
+            (LevelA2, Some(LevelA1)),
+            (LevelA1, Some(Top)),
+            (Top, None),
+            (LevelB1, Some(Top)),
+        ];
+        assert!(validate_topology(
+            &states,
+            &[(Top, LevelA1)],
+            &[LevelA1],
+            &[(LevelA2, LevelB1)],
+            &[(Top, 0), (LevelA2, 2)]
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn reports_every_issue() {
+        let states = vec![
+            (LevelA1, Some(LevelA2)),
+            (LevelA2, Some(LevelA1)),
+            (Top, Some(LevelB1)),
+            (LevelA1, None),
+        ];
+        assert_eq!(
+            validate_topology(&states, &[], &[], &[], &[]),
+            vec![
+                TopologyIssue::DuplicateState(LevelA1),
+                TopologyIssue::NoRoot,
+                TopologyIssue::MissingParent {
+                    state: Top,
+                    parent: LevelB1
+                },
+                TopologyIssue::Cycle(vec![LevelA1, LevelA2]),
+            ]
+        );
+
+        // Roots are found regardless of the order they were added
+        let states = vec![
+            (LevelA2, Some(LevelA1)),
+            (LevelA1, None),
+            (LevelB1, Some(Top)),
+            (Top, None),
+        ];
+        assert_eq!(
+            validate_topology(&states, &[], &[], &[], &[]),
+            vec![TopologyIssue::MultipleRoots(vec![LevelA1, Top])]
+        );
+    }
+
+    #[test]
+    fn initial_children_must_be_direct_children() {
+        let states = vec![
+            (Top, None),
+            (LevelA1, Some(Top)),
+            (LevelA2, Some(LevelA1)),
+            (LevelB1, Some(Top)),
+        ];
+        assert_eq!(
+            validate_topology(
+                &states,
+                &[(Top, LevelA2), (LevelA1, LevelA2), (LevelA1, LevelA2)],
+                &[],
+                &[],
+                &[]
+            ),
+            vec![
+                TopologyIssue::InvalidInitialChild {
+                    parent: Top,
+                    child: LevelA2
+                },
+                TopologyIssue::MultipleInitialChildren(LevelA1),
+            ]
+        );
+    }
+
+    #[test]
+    fn transition_states_must_be_added() {
+        let states = vec![(Top, None), (LevelA1, Some(Top))];
+        assert_eq!(
+            validate_topology(
+                &states,
+                &[],
+                &[],
+                &[(LevelA1, Top), (LevelA1, LevelB1), (LevelA2, Top)],
+                &[]
+            ),
+            vec![
+                TopologyIssue::UnknownTransitionTarget {
+                    state: LevelA1,
+                    target: LevelB1
+                },
+                TopologyIssue::UnknownTransitionSource {
+                    state: LevelA2,
+                    target: Top
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_depths_must_match() {
+        let states = vec![(Top, None), (LevelA1, Some(Top)), (LevelA2, Some(LevelA1))];
+        assert_eq!(
+            validate_topology(
+                &states,
+                &[],
+                &[],
+                &[],
+                &[(Top, 0), (LevelA1, 1), (LevelA2, 1), (LevelB1, 1)]
+            ),
+            vec![TopologyIssue::DepthMismatch {
+                state: LevelA2,
+                declared: 1,
+                actual: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn history_needs_children() {
+        let states = vec![(Top, None), (LevelA1, Some(Top))];
+        assert_eq!(
+            validate_topology(&states, &[], &[Top, LevelA1, LevelB1], &[], &[]),
+            vec![
+                TopologyIssue::InvalidHistoryState(LevelA1),
+                TopologyIssue::InvalidHistoryState(LevelB1),
+            ]
+        );
+    }
+
+    #[test]
+    fn descendants_of_broken_states_are_unreachable() {
+        let states = vec![
+            (Top, None),
+            (LevelA2, Some(LevelA1)),
+            (LevelA1, Some(LevelB1)),
+        ];
+        assert_eq!(
+            validate_topology(&states, &[], &[], &[], &[]),
+            vec![
+                TopologyIssue::MissingParent {
+                    state: LevelA1,
+                    parent: LevelB1
+                },
+                TopologyIssue::Unreachable(LevelA2),
+            ]
+        );
+        assert_eq!(
+            TopologyIssue::Cycle(vec![LevelA1, LevelA2]).to_string(),
+            "Cycle of parents: LevelA1 -> LevelA2"
+        );
+    }
+}
@@ -0,0 +1,559 @@
+//! Structured trace of how the engine handled an event and the formats it can be written in.
+//! Every format can be parsed back into a TraceRecord so tooling can consume the traces.
+
+/// Summary of a handled event that resulted in a change of state.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceRecord {
+    pub hsm_name: String,
+    /// State the event was dispatched to
+    pub state: String,
+    pub event: String,
+    /// States exited, in order
+    pub exited: Vec<String>,
+    /// States entered, in order
+    pub entered: Vec<String>,
+    /// State settled on (if any)
+    pub started: Option<String>,
+}
+
+/// How a TraceRecord is rendered into a single line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// `Hsm: State(Event): [A(EXIT)], [B(ENTER), B(START)]`.
+    /// Delimiters within names are escaped with a backslash.
+    #[default]
+    Human,
+    /// One JSON object per line
+    JsonLines,
+    /// Compact `key=value` pairs. Lists are comma separated (commas within names escaped with a backslash).
+    KeyValue,
+}
+
+impl TraceFormat {
+    pub fn format(&self, record: &TraceRecord) -> String {
+        match self {
+            TraceFormat::Human => format_human(record),
+            TraceFormat::JsonLines => format_json(record),
+            TraceFormat::KeyValue => format_key_value(record),
+        }
+    }
+
+    /// Parse a line written by this format back into a record.
+    /// None if the line is not a valid trace in this format.
+    pub fn parse(&self, line: &str) -> Option<TraceRecord> {
+        match self {
+            TraceFormat::Human => parse_human(line),
+            TraceFormat::JsonLines => parse_json(line),
+            TraceFormat::KeyValue => parse_key_value(line),
+        }
+    }
+}
+
+// Start of Human //
+
+/// Characters delimiting the parts of a human line, escaped with a backslash within values
+const HUMAN_DELIMITERS: &[char] = &[':', '(', ')', '[', ']', ','];
+
+fn format_human(record: &TraceRecord) -> String {
+    let escape = |value: &str| escape(value, HUMAN_DELIMITERS);
+    let mut line = format!(
+        "{}: {}({}): ",
+        escape(&record.hsm_name),
+        escape(&record.state),
+        escape(&record.event)
+    );
+    if !record.exited.is_empty() {
+        let exits: Vec<String> = record
+            .exited
+            .iter()
+            .map(|state| format!("{}(EXIT)", escape(state)))
+            .collect();
+        line.push_str(format!("[{}], ", exits.join(", ")).as_str());
+    }
+    if record.entered.is_empty() && record.started.is_none() {
+        return line;
+    }
+
+    let mut enters: Vec<String> = record
+        .entered
+        .iter()
+        .map(|state| format!("{}(ENTER)", escape(state)))
+        .collect();
+    if let Some(started) = &record.started {
+        enters.push(format!("{}(START)", escape(started)));
+    }
+    line.push_str(format!("[{}]", enters.join(", ")).as_str());
+    line
+}
+
+fn parse_human(line: &str) -> Option<TraceRecord> {
+    let (hsm_name, rest) = split_unescaped(line, ": ")?;
+    let (state, rest) = split_unescaped(rest, "(")?;
+    let (event, mut rest) = split_unescaped(rest, "): ")?;
+
+    let mut record = TraceRecord {
+        hsm_name: unescape(hsm_name),
+        state: unescape(state),
+        event: unescape(event),
+        ..Default::default()
+    };
+
+    // Up to two [...] blocks of steps, separated by ", "
+    loop {
+        rest = rest.trim();
+        if rest.is_empty() {
+            break;
+        }
+        let (block, after) = split_unescaped(rest.strip_prefix('[')?, "]")?;
+        rest = after.strip_prefix(',').unwrap_or(after);
+
+        let mut steps = block;
+        while !steps.is_empty() {
+            let (step, after) = split_unescaped(steps, ", ").unwrap_or((steps, ""));
+            steps = after;
+            let (state, action) = split_unescaped(step, "(")?;
+            let state = unescape(state);
+            match action.strip_suffix(')')? {
+                "EXIT" => record.exited.push(state),
+                "ENTER" => record.entered.push(state),
+                "START" => record.started = Some(state),
+                _ => return None,
+            }
+        }
+    }
+    Some(record)
+}
+
+// End of Human //
+
+// Start of Escaping //
+
+/// Prefix the backslash and every special character with a backslash
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Split around the first occurrence of the delimiter that is not escaped.
+/// Both halves are still escaped.
+fn split_unescaped<'a>(value: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    let mut chars = value.char_indices();
+    while let Some((index, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if value[index..].starts_with(delimiter) {
+            return Some((&value[..index], &value[index + delimiter.len()..]));
+        }
+    }
+    None
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+// End of Escaping //
+
+// Start of JSON //
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(format!("\\u{:04x}", c as u32).as_str()),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn json_list(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|value| json_string(value)).collect();
+    format!("[{}]", values.join(","))
+}
+
+fn format_json(record: &TraceRecord) -> String {
+    format!(
+        "{{\"hsm\":{},\"state\":{},\"event\":{},\"exited\":{},\"entered\":{},\"started\":{}}}",
+        json_string(&record.hsm_name),
+        json_string(&record.state),
+        json_string(&record.event),
+        json_list(&record.exited),
+        json_list(&record.entered),
+        match &record.started {
+            None => "null".to_string(),
+            Some(started) => json_string(started),
+        }
+    )
+}
+
+/// Values that can appear in a trace's JSON object
+enum JsonValue {
+    Null,
+    String(String),
+    List(Vec<String>),
+}
+
+/// Bare-bones parser for the (flat) objects written by format_json
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Option<()> {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&expected).map(|_| ())
+    }
+
+    fn parse_string(&mut self) -> Option<String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.chars.next()? {
+                '"' => return Some(value),
+                '\\' => match self.chars.next()? {
+                    'n' => value.push('\n'),
+                    'r' => value.push('\r'),
+                    't' => value.push('\t'),
+                    'u' => {
+                        let code: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        value.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+                    }
+                    escaped => value.push(escaped),
+                },
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Option<JsonValue> {
+        self.skip_whitespace();
+        match self.chars.peek()? {
+            '"' => self.parse_string().map(JsonValue::String),
+            'n' => {
+                let null: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                (null == "null").then_some(JsonValue::Null)
+            }
+            '[' => {
+                self.expect('[')?;
+                let mut values = vec![];
+                if self.expect(']').is_some() {
+                    return Some(JsonValue::List(values));
+                }
+                loop {
+                    values.push(self.parse_string()?);
+                    if self.expect(']').is_some() {
+                        return Some(JsonValue::List(values));
+                    }
+                    self.expect(',')?;
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+fn parse_json(line: &str) -> Option<TraceRecord> {
+    let mut parser = JsonParser {
+        chars: line.chars().peekable(),
+    };
+    let mut record = TraceRecord::default();
+    parser.expect('{')?;
+    if parser.expect('}').is_some() {
+        return None;
+    }
+    loop {
+        let key = parser.parse_string()?;
+        parser.expect(':')?;
+        match (key.as_str(), parser.parse_value()?) {
+            ("hsm", JsonValue::String(value)) => record.hsm_name = value,
+            ("state", JsonValue::String(value)) => record.state = value,
+            ("event", JsonValue::String(value)) => record.event = value,
+            ("exited", JsonValue::List(values)) => record.exited = values,
+            ("entered", JsonValue::List(values)) => record.entered = values,
+            ("started", JsonValue::String(value)) => record.started = Some(value),
+            ("started", JsonValue::Null) => record.started = None,
+            _ => return None,
+        }
+        if parser.expect('}').is_some() {
+            break;
+        }
+        parser.expect(',')?;
+    }
+    parser.skip_whitespace();
+    parser.chars.peek().is_none().then_some(record)
+}
+
+// End of JSON //
+
+// Start of Key Value //
+
+fn key_value_quote(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '=' || c == '"' || c == '\\');
+    if !needs_quotes {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Written in place of an empty element, so `[""]` does not read back as `[]`.
+/// Escaping never writes it otherwise, as 'e' is not special.
+const EMPTY_LIST_ELEMENT: &str = r"\e";
+
+/// Comma separated, commas within the values escaped
+fn key_value_list(values: &[String]) -> String {
+    let values: Vec<String> = values
+        .iter()
+        .map(|value| match value.as_str() {
+            "" => EMPTY_LIST_ELEMENT.to_string(),
+            value => escape(value, &[',']),
+        })
+        .collect();
+    values.join(",")
+}
+
+fn format_key_value(record: &TraceRecord) -> String {
+    let pairs = [
+        ("hsm", record.hsm_name.clone()),
+        ("state", record.state.clone()),
+        ("event", record.event.clone()),
+        ("exited", key_value_list(&record.exited)),
+        ("entered", key_value_list(&record.entered)),
+        ("started", record.started.clone().unwrap_or_default()),
+    ];
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, key_value_quote(value)))
+        .collect();
+    pairs.join(" ")
+}
+
+/// Split `a=b c="d e"` into its pairs, un-quoting as we go
+fn split_key_values(line: &str) -> Option<Vec<(String, String)>> {
+    let mut pairs = vec![];
+    let mut chars = line.trim().chars().peekable();
+    while chars.peek().is_some() {
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => value.push(chars.next()?),
+                    c => value.push(c),
+                }
+            }
+            chars.next_if(|c| c.is_whitespace());
+        } else {
+            value = chars.by_ref().take_while(|c| !c.is_whitespace()).collect();
+        }
+        pairs.push((key.trim().to_string(), value));
+    }
+    Some(pairs)
+}
+
+fn split_list(value: String) -> Vec<String> {
+    let mut values = vec![];
+    let mut rest = value.as_str();
+    while !rest.is_empty() {
+        let (value, after) = split_unescaped(rest, ",").unwrap_or((rest, ""));
+        values.push(match value {
+            EMPTY_LIST_ELEMENT => String::new(),
+            value => unescape(value),
+        });
+        rest = after;
+    }
+    values
+}
+
+fn parse_key_value(line: &str) -> Option<TraceRecord> {
+    let mut record = TraceRecord::default();
+    let mut found_hsm = false;
+    for (key, value) in split_key_values(line)? {
+        match key.as_str() {
+            "hsm" => {
+                found_hsm = true;
+                record.hsm_name = value
+            }
+            "state" => record.state = value,
+            "event" => record.event = value,
+            "exited" => record.exited = split_list(value),
+            "entered" => record.entered = split_list(value),
+            "started" => record.started = (!value.is_empty()).then_some(value),
+            _ => return None,
+        }
+    }
+    found_hsm.then_some(record)
+}
+
+// End of Key Value //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_record() -> TraceRecord {
+        TraceRecord {
+            hsm_name: "LightControllerHsm".to_string(),
+            state: "DIMMER".to_string(),
+            event: "TurnOff".to_string(),
+            exited: vec!["DIMMER".to_string(), "ON".to_string()],
+            entered: vec!["OFF".to_string()],
+            started: Some("OFF".to_string()),
+        }
+    }
+
+    #[test]
+    fn human_format() {
+        let record = create_record();
+        let line = TraceFormat::Human.format(&record);
+        assert_eq!(
+            line,
+            "LightControllerHsm: DIMMER(TurnOff): [DIMMER(EXIT), ON(EXIT)], [OFF(ENTER), OFF(START)]"
+        );
+        assert_eq!(TraceFormat::Human.parse(&line), Some(record));
+
+        // Transitions to a child state do not exit anything
+        let record = TraceRecord {
+            exited: vec![],
+            ..create_record()
+        };
+        let line = TraceFormat::Human.format(&record);
+        assert_eq!(
+            line,
+            "LightControllerHsm: DIMMER(TurnOff): [OFF(ENTER), OFF(START)]"
+        );
+        assert_eq!(TraceFormat::Human.parse(&line), Some(record));
+    }
+
+    #[test]
+    fn json_format() {
+        let record = create_record();
+        let line = TraceFormat::JsonLines.format(&record);
+        assert_eq!(
+            line,
+            r#"{"hsm":"LightControllerHsm","state":"DIMMER","event":"TurnOff","exited":["DIMMER","ON"],"entered":["OFF"],"started":"OFF"}"#
+        );
+        assert_eq!(TraceFormat::JsonLines.parse(&line), Some(record));
+
+        let record = TraceRecord {
+            hsm_name: "Quoted \"Hsm\"\n".to_string(),
+            started: None,
+            ..create_record()
+        };
+        let line = TraceFormat::JsonLines.format(&record);
+        assert_eq!(TraceFormat::JsonLines.parse(&line), Some(record));
+        assert!(TraceFormat::JsonLines.parse("{\"hsm\": 5}").is_none());
+    }
+
+    #[test]
+    fn key_value_format() {
+        let record = create_record();
+        let line = TraceFormat::KeyValue.format(&record);
+        assert_eq!(
+            line,
+            "hsm=LightControllerHsm state=DIMMER event=TurnOff exited=DIMMER,ON entered=OFF started=OFF"
+        );
+        assert_eq!(TraceFormat::KeyValue.parse(&line), Some(record));
+
+        let record = TraceRecord {
+            hsm_name: "Light Hsm".to_string(),
+            exited: vec![],
+            started: None,
+            ..create_record()
+        };
+        let line = TraceFormat::KeyValue.format(&record);
+        assert_eq!(
+            line,
+            "hsm=\"Light Hsm\" state=DIMMER event=TurnOff exited=\"\" entered=OFF started=\"\""
+        );
+        assert_eq!(TraceFormat::KeyValue.parse(&line), Some(record));
+    }
+
+    #[test]
+    fn delimiters_round_trip() {
+        let record = TraceRecord {
+            hsm_name: "Light: Hsm".to_string(),
+            state: "DIMMER(50%)".to_string(),
+            event: "Turn, Off): [now]".to_string(),
+            exited: vec!["A,B".to_string(), "C\\".to_string()],
+            entered: vec!["D(ENTER)".to_string(), "E, F".to_string()],
+            started: Some("[G]".to_string()),
+        };
+        let line = TraceFormat::Human.format(&record);
+        assert_eq!(
+            line,
+            r"Light\: Hsm: DIMMER\(50%\)(Turn\, Off\)\: \[now\]): [A\,B(EXIT), C\\(EXIT)], [D\(ENTER\)(ENTER), E\, F(ENTER), \[G\](START)]"
+        );
+        for format in [
+            TraceFormat::Human,
+            TraceFormat::JsonLines,
+            TraceFormat::KeyValue,
+        ] {
+            let line = format.format(&record);
+            assert_eq!(format.parse(&line), Some(record.clone()), "{}", line);
+        }
+    }
+
+    #[test]
+    fn empty_elements_round_trip() {
+        let mut record = TraceRecord {
+            hsm_name: "TestHsm".to_string(),
+            state: "LevelA2".to_string(),
+            event: "A".to_string(),
+            exited: vec![String::new()],
+            entered: vec![],
+            started: None,
+        };
+        assert_eq!(
+            TraceFormat::KeyValue.format(&record),
+            r#"hsm=TestHsm state=LevelA2 event=A exited="\\e" entered="" started="""#
+        );
+        for (exited, entered) in [
+            (vec![String::new()], vec![]),
+            (vec![], vec![String::new()]),
+            (
+                vec!["e".to_string(), String::new()],
+                vec![String::new(), String::new()],
+            ),
+        ] {
+            record.exited = exited;
+            record.entered = entered;
+            for format in [
+                TraceFormat::Human,
+                TraceFormat::JsonLines,
+                TraceFormat::KeyValue,
+            ] {
+                let line = format.format(&record);
+                assert_eq!(format.parse(&line), Some(record.clone()), "{}", line);
+            }
+        }
+    }
+}
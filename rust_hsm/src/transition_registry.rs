@@ -0,0 +1,147 @@
+//! Registry of the states each state is allowed to change to (and on which events).
+//! Enforced by the engine at runtime, and exportable so tooling can draw the transition graph.
+use std::collections::HashMap;
+
+use crate::state::StateConstraint;
+
+/// States that never declared their targets may change to any state.
+/// Events only label transitions (i.e. for diagrams): labelling one never restricts its source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransitionRegistry<StateT: StateConstraint> {
+    /// Sources, in the order they were first declared or labelled
+    sources: Vec<StateT>,
+    allowed_targets: HashMap<StateT, Vec<StateT>>,
+    /// Targets labelled with an event, per source (in the order labelled)
+    labelled_targets: HashMap<StateT, Vec<StateT>>,
+    /// Names of the events causing a (source, target) transition. Only used for documentation.
+    events: HashMap<(StateT, StateT), Vec<String>>,
+}
+
+impl<StateT: StateConstraint> Default for TransitionRegistry<StateT> {
+    fn default() -> Self {
+        Self {
+            sources: vec![],
+            allowed_targets: HashMap::new(),
+            labelled_targets: HashMap::new(),
+            events: HashMap::new(),
+        }
+    }
+}
+
+impl<StateT: StateConstraint> TransitionRegistry<StateT> {
+    /// Replaces whatever source declared before
+    pub(crate) fn declare(&mut self, source: StateT, targets: Vec<StateT>) {
+        self.add_source(source);
+        self.allowed_targets.insert(source, targets);
+    }
+
+    /// Names the event source changes to target on, without allowing or restricting anything
+    pub(crate) fn label_event(&mut self, source: StateT, event_name: &str, target: StateT) {
+        self.add_source(source);
+        let targets = self.labelled_targets.entry(source).or_default();
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+        let event_names = self.events.entry((source, target)).or_default();
+        if !event_names.iter().any(|name| name == event_name) {
+            event_names.push(event_name.to_string());
+        }
+    }
+
+    fn add_source(&mut self, source: StateT) {
+        if !self.sources.contains(&source) {
+            self.sources.push(source);
+        }
+    }
+
+    /// None if the state never declared its targets (i.e. it is unrestricted)
+    pub fn allowed_targets(&self, source: StateT) -> Option<&[StateT]> {
+        self.allowed_targets.get(&source).map(Vec::as_slice)
+    }
+
+    pub fn is_allowed(&self, source: StateT, target: StateT) -> bool {
+        self.allowed_targets(source)
+            .is_none_or(|targets| targets.contains(&target))
+    }
+
+    /// Names of the events source changes to target on (in the order labelled)
+    pub fn events(&self, source: StateT, target: StateT) -> &[String] {
+        self.events
+            .get(&(source, target))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Every declared or labelled (source, target) pair, in the order declared then labelled
+    pub fn edges(&self) -> Vec<(StateT, StateT)> {
+        self.sources
+            .iter()
+            .flat_map(|source| {
+                let allowed_targets = self.allowed_targets(*source).unwrap_or_default();
+                let labelled_targets = self
+                    .labelled_targets
+                    .get(source)
+                    .map_or(&[][..], Vec::as_slice)
+                    .iter()
+                    .filter(|target| !allowed_targets.contains(target));
+                allowed_targets
+                    .iter()
+                    .chain(labelled_targets)
+                    .map(move |target| (*source, *target))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::ExampleStates::{self, *};
+
+    #[test]
+    fn declared_transitions() {
+        let mut registry = TransitionRegistry::<ExampleStates>::default();
+        registry.declare(LevelA2, vec![LevelB1]);
+        registry.declare(LevelB1, vec![LevelA1, LevelA2]);
+
+        assert!(registry.is_allowed(LevelA2, LevelB1));
+        assert!(!registry.is_allowed(LevelA2, LevelA1));
+        // Never declared
+        assert!(registry.is_allowed(LevelA1, Top));
+        assert_eq!(registry.allowed_targets(LevelA1), None);
+        assert_eq!(
+            registry.edges(),
+            vec![(LevelA2, LevelB1), (LevelB1, LevelA1), (LevelB1, LevelA2)]
+        );
+
+        registry.declare(LevelA2, vec![]);
+        assert!(!registry.is_allowed(LevelA2, LevelB1));
+        assert_eq!(
+            registry.edges(),
+            vec![(LevelB1, LevelA1), (LevelB1, LevelA2)]
+        );
+    }
+
+    #[test]
+    fn labelled_events() {
+        let mut registry = TransitionRegistry::<ExampleStates>::default();
+        registry.label_event(LevelA2, "F", LevelB1);
+        registry.label_event(LevelA2, "A", LevelB1);
+        registry.label_event(LevelA2, "F", LevelB1);
+        registry.label_event(LevelB1, "C", Top);
+
+        // Labels restrict nothing
+        assert!(registry.is_allowed(LevelA2, LevelA1));
+        assert_eq!(registry.allowed_targets(LevelA2), None);
+        assert_eq!(registry.edges(), vec![(LevelA2, LevelB1), (LevelB1, Top)]);
+
+        // Labelled edges are drawn after the declared ones, once
+        registry.declare(LevelA2, vec![LevelA1, LevelB1]);
+        assert!(!registry.is_allowed(LevelA2, Top));
+        assert_eq!(
+            registry.edges(),
+            vec![(LevelA2, LevelA1), (LevelA2, LevelB1), (LevelB1, Top)]
+        );
+        assert_eq!(registry.events(LevelA2, LevelB1), ["F", "A"]);
+        assert!(registry.events(LevelB1, LevelA1).is_empty());
+    }
+}
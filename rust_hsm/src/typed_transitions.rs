@@ -0,0 +1,114 @@
+//! Optional, typestate-flavoured way for states to change state.
+//! Every state type declares where it may go (`impl TransitionTo<Off> for Dimmer {}`),
+//! so changing state to anywhere else does not compile.
+use std::marker::PhantomData;
+
+use crate::{
+    errors::{HSMError, HSMResult},
+    events::StateEventConstraint,
+    state::StateConstraint,
+    state_engine_delegate::WeakDelegate,
+};
+
+/// Ties a state type to its variant of the state enum
+pub trait TypedState<StateT: StateConstraint> {
+    const STATE: StateT;
+}
+
+/// Declares that the implementing state may change state to TargetT
+pub trait TransitionTo<TargetT> {}
+
+/// A delegate which only lets SourceT change state along its declared transitions.
+/// Hand one to each state in place of a WeakDelegate.
+pub struct TypedDelegate<SourceT, StateT, EventT> {
+    delegate: WeakDelegate<StateT, EventT>,
+    source: PhantomData<fn() -> SourceT>,
+}
+
+impl<SourceT, StateT: StateConstraint, EventT: StateEventConstraint>
+    TypedDelegate<SourceT, StateT, EventT>
+{
+    pub fn new(delegate: WeakDelegate<StateT, EventT>) -> Self {
+        Self {
+            delegate,
+            source: PhantomData,
+        }
+    }
+
+    /// # Brief
+    /// Change state to TargetT. Only compiles if `SourceT: TransitionTo<TargetT>`.
+    /// ```compile_fail
+    /// use rust_hsm::{examples::*, typed_transitions::*};
+    /// struct A1;
+    /// struct B1;
+    /// impl TypedState<ExampleStates> for B1 {
+    ///     const STATE: ExampleStates = ExampleStates::LevelB1;
+    /// }
+    /// // Missing: impl TransitionTo<B1> for A1 {}
+    /// fn leave_a1(delegate: TypedDelegate<A1, ExampleStates, ExampleEvents>) {
+    ///     delegate.transition::<B1>().unwrap();
+    /// }
+    /// ```
+    pub fn transition<TargetT>(&self) -> HSMResult<(), StateT>
+    where
+        SourceT: TransitionTo<TargetT>,
+        TargetT: TypedState<StateT>,
+    {
+        match self.delegate.upgrade() {
+            None => Err(HSMError::DelegateUpgradeFail(format!(
+                "transition to {}",
+                TargetT::STATE
+            ))),
+            Some(delegate) => delegate.change_state(TargetT::STATE),
+        }
+    }
+
+    /// For everything else the delegate offers (i.e. internal_handle_event)
+    pub fn untyped(&self) -> &WeakDelegate<StateT, EventT> {
+        &self.delegate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        examples::{ExampleEvents, ExampleStates},
+        state_engine_delegate::{delegate_test_utils::MockedDelegate, SharedDelegate},
+    };
+    use std::rc::Rc;
+
+    struct A1;
+    struct B1;
+
+    impl TypedState<ExampleStates> for A1 {
+        const STATE: ExampleStates = ExampleStates::LevelA1;
+    }
+
+    impl TypedState<ExampleStates> for B1 {
+        const STATE: ExampleStates = ExampleStates::LevelB1;
+    }
+
+    impl TransitionTo<B1> for A1 {}
+
+    #[test]
+    fn transition() {
+        let mocked_delegate = Rc::new(MockedDelegate::<ExampleStates, ExampleEvents>::new());
+        let shared_delegate: SharedDelegate<ExampleStates, ExampleEvents> = mocked_delegate.clone();
+        let typed_delegate = TypedDelegate::<A1, _, _>::new(Rc::downgrade(&shared_delegate));
+
+        typed_delegate.transition::<B1>().unwrap();
+        // typed_delegate.transition::<A1>() does not compile
+        assert_eq!(
+            *mocked_delegate.change_states_requested.borrow(),
+            vec![ExampleStates::LevelB1]
+        );
+
+        drop(shared_delegate);
+        drop(mocked_delegate);
+        assert!(matches!(
+            typed_delegate.transition::<B1>(),
+            Err(HSMError::DelegateUpgradeFail(_))
+        ));
+    }
+}
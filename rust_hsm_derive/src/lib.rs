@@ -0,0 +1,336 @@
+//! Derive macros removing the boilerplate of defining the states and events of an HSM.
+//! Re-exported by rust_hsm, use them from there.
+use std::collections::HashMap;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, Ident};
+
+/// # Brief
+/// Implements `StateConstraint` for a fieldless enum.
+/// The remaining requirements (Copy, Eq, Hash and Display) still need to be derived.
+/// # Errors (at compile time)
+/// * Not an enum, or an enum without variants
+/// * A variant holding data. States are used as keys by the engine.
+/// # Example
+/// ```ignore
+/// #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, strum::Display, HsmState)]
+/// enum LightStates {
+///     Top,
+///     On,
+///     Off,
+/// }
+/// ```
+#[proc_macro_derive(HsmState)]
+pub fn derive_hsm_state(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_hsm_state(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// # Brief
+/// Implements `StateEventConstraint` for an enum or struct.
+/// For enums, the event name is the name of the variant (i.e. regardless of its data or Display).
+/// Structs fall back to their Display.
+#[proc_macro_derive(HsmEvent)]
+pub fn derive_hsm_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_hsm_event(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// # Brief
+/// Declares the hierarchy on the state enum itself and implements `StateHierarchy` for it.
+/// Also implements `StateConstraint` (no need to derive HsmState).
+/// * `#[parent(State)]` on every state but the top one
+/// * `#[initial]` on the child entered in place of its parent
+/// # Errors (at compile time)
+/// Everything HsmState checks, plus unknown parents, zero or multiple top states,
+/// cycles of parents and multiple initial children for a parent.
+/// # Example
+/// ```ignore
+/// #[hsm_states]
+/// #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, strum::Display)]
+/// enum LightStates {
+///     Top,
+///     #[parent(Top)]
+///     On,
+///     #[parent(On)]
+///     #[initial]
+///     Dimmer,
+///     #[parent(Top)]
+///     Off,
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn hsm_states(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    if !args.is_empty() {
+        return Error::new(
+            proc_macro2::Span::call_site(),
+            "hsm_states does not take any arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+    match expand_hsm_states(&mut input) {
+        Ok(tokens) => tokens.into(),
+        // Keep the enum around, so the only errors are the ones about the declaration
+        Err(err) => {
+            let compile_error = err.to_compile_error();
+            quote! { #input #compile_error }.into()
+        }
+    }
+}
+
+/// A variant of the enum, along with what was declared on it
+struct DeclaredState {
+    name: Ident,
+    parent: Option<Ident>,
+    initial: Option<proc_macro2::Span>,
+}
+
+fn expand_hsm_states(input: &mut DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let declared_states = take_declared_states(input)?;
+    let state_constraint = expand_hsm_state(input)?;
+    let depths = validate_declared_states(&input.ident, &declared_states)?;
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let parents = declared_states.iter().map(|state| {
+        let state_name = &state.name;
+        match &state.parent {
+            None => quote! { (Self::#state_name, None) },
+            Some(parent) => quote! { (Self::#state_name, Some(Self::#parent)) },
+        }
+    });
+    let depths = declared_states.iter().map(|state| {
+        let state_name = &state.name;
+        let depth = depths[&state.name];
+        quote! { (Self::#state_name, #depth) }
+    });
+    let initial_children = declared_states
+        .iter()
+        .filter(|state| state.initial.is_some())
+        .map(|state| {
+            let state_name = &state.name;
+            let parent = state.parent.as_ref().unwrap();
+            quote! { (Self::#parent, Self::#state_name) }
+        });
+    Ok(quote! {
+        #input
+        #state_constraint
+        impl #impl_generics ::rust_hsm::state::StateHierarchy for #name #type_generics #where_clause {
+            const PARENTS: &'static [(Self, Option<Self>)] = &[#(#parents),*];
+            const DEPTHS: &'static [(Self, usize)] = &[#(#depths),*];
+            const INITIAL_CHILDREN: &'static [(Self, Self)] = &[#(#initial_children),*];
+        }
+    })
+}
+
+/// Strips #[parent(..)] and #[initial] off the variants, returning what they declared
+fn take_declared_states(input: &mut DeriveInput) -> syn::Result<Vec<DeclaredState>> {
+    let Data::Enum(data) = &mut input.data else {
+        return Err(Error::new(
+            input.ident.span(),
+            "hsm_states can only be used on enums",
+        ));
+    };
+    let mut errors: Vec<Error> = vec![];
+    let mut declared_states = vec![];
+    for variant in data.variants.iter_mut() {
+        let mut declared_state = DeclaredState {
+            name: variant.ident.clone(),
+            parent: None,
+            initial: None,
+        };
+        let mut remaining_attrs = vec![];
+        for attr in variant.attrs.drain(..) {
+            if attr.path().is_ident("parent") {
+                if declared_state.parent.is_some() {
+                    errors.push(Error::new(attr.span(), "A state can only have one parent"));
+                    continue;
+                }
+                match attr.parse_args::<Ident>() {
+                    Ok(parent) => declared_state.parent = Some(parent),
+                    Err(err) => errors.push(err),
+                }
+            } else if attr.path().is_ident("initial") {
+                match attr.meta.require_path_only() {
+                    Ok(path) => declared_state.initial = Some(path.span()),
+                    Err(err) => errors.push(err),
+                }
+            } else {
+                remaining_attrs.push(attr);
+            }
+        }
+        variant.attrs = remaining_attrs;
+        declared_states.push(declared_state);
+    }
+    combine_errors(errors).map_or(Ok(declared_states), Err)
+}
+
+fn combine_errors(errors: Vec<Error>) -> Option<Error> {
+    errors.into_iter().reduce(|mut combined, err| {
+        combined.combine(err);
+        combined
+    })
+}
+
+/// Reports every problem at once. Returns the depth of every state.
+fn validate_declared_states(
+    enum_name: &Ident,
+    declared_states: &[DeclaredState],
+) -> syn::Result<HashMap<Ident, usize>> {
+    let mut errors: Vec<Error> = vec![];
+    let parents: HashMap<&Ident, Option<&Ident>> = declared_states
+        .iter()
+        .map(|state| (&state.name, state.parent.as_ref()))
+        .collect();
+
+    let tops: Vec<&Ident> = declared_states
+        .iter()
+        .filter(|state| state.parent.is_none())
+        .map(|state| &state.name)
+        .collect();
+    if tops.is_empty() {
+        errors.push(Error::new(
+            enum_name.span(),
+            "One state needs to be the top state (the one without #[parent])",
+        ));
+    }
+    for extra_top in tops.iter().skip(1) {
+        errors.push(Error::new(
+            extra_top.span(),
+            format!(
+                "Only one state can be without #[parent], {} already is the top state",
+                tops[0]
+            ),
+        ));
+    }
+
+    let mut initial_children: HashMap<&Ident, &Ident> = HashMap::new();
+    for state in declared_states {
+        if let Some(parent) = &state.parent {
+            if !parents.contains_key(parent) {
+                errors.push(Error::new(
+                    parent.span(),
+                    format!("Unknown parent state {}", parent),
+                ));
+            }
+        }
+        if let Some(initial_span) = state.initial {
+            match &state.parent {
+                None => errors.push(Error::new(
+                    initial_span,
+                    "The top state can not be #[initial]",
+                )),
+                Some(parent) => {
+                    if let Some(other_child) = initial_children.insert(parent, &state.name) {
+                        errors.push(Error::new(
+                            initial_span,
+                            format!("{} already is the initial child of {}", other_child, parent),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut depths: HashMap<Ident, usize> = HashMap::new();
+    for state in declared_states {
+        let mut depth = 0;
+        let mut current_state = &state.name;
+        while let Some(Some(parent)) = parents.get(current_state) {
+            depth += 1;
+            current_state = parent;
+            if depth > declared_states.len() {
+                errors.push(Error::new(
+                    state.name.span(),
+                    format!(
+                        "State {} never reaches the top state, its parents form a cycle",
+                        state.name
+                    ),
+                ));
+                break;
+            }
+        }
+        depths.insert(state.name.clone(), depth);
+    }
+
+    combine_errors(errors).map_or(Ok(depths), Err)
+}
+
+fn expand_hsm_state(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "HsmState can only be derived for enums",
+            ))
+        }
+    };
+    if data.variants.is_empty() {
+        return Err(Error::new(
+            input.ident.span(),
+            "HsmState needs at least one variant (the top state)",
+        ));
+    }
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(Error::new(
+                variant.fields.span(),
+                "HsmState variants can not hold data, states are used as keys by the engine",
+            ));
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rust_hsm::state::StateConstraint for #name #type_generics #where_clause {}
+    })
+}
+
+fn expand_hsm_event(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let body = match &input.data {
+        Data::Enum(data) => {
+            let arms = data.variants.iter().map(|variant| {
+                let variant_name = &variant.ident;
+                let pattern = match &variant.fields {
+                    Fields::Unit => quote! { Self::#variant_name },
+                    Fields::Unnamed(_) => quote! { Self::#variant_name(..) },
+                    Fields::Named(_) => quote! { Self::#variant_name { .. } },
+                };
+                let event_name = variant_name.to_string();
+                quote! { #pattern => #event_name.to_string(), }
+            });
+            quote! {
+                fn get_event_name(&self) -> String {
+                    match *self {
+                        #(#arms)*
+                    }
+                }
+            }
+        }
+        Data::Struct(_) => quote! {},
+        Data::Union(_) => {
+            return Err(Error::new(
+                input.ident.span(),
+                "HsmEvent can only be derived for enums and structs",
+            ))
+        }
+    };
+    Ok(quote! {
+        impl #impl_generics ::rust_hsm::events::StateEventConstraint for #name #type_generics #where_clause {
+            #body
+        }
+    })
+}
@@ -0,0 +1,137 @@
+use rust_hsm::{
+    builder::HsmBuilder,
+    errors::HSMError,
+    events::StateEventConstraint,
+    hsm_states,
+    state::{StateHierarchy, StateIF},
+    topology_validation::TopologyIssue,
+    HsmEvent, HsmState,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, strum::Display, HsmState)]
+enum DoorStates {
+    Top,
+    Open,
+    Closed,
+}
+
+#[allow(dead_code)]
+#[derive(strum::Display, HsmEvent)]
+enum DoorEvents {
+    #[strum(to_string = "open the door")]
+    Open,
+    Lock(u16),
+    Paint {
+        color: String,
+    },
+}
+
+#[derive(HsmEvent)]
+struct Knock;
+
+impl std::fmt::Display for Knock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Knock knock")
+    }
+}
+
+#[hsm_states]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, strum::Display)]
+enum LightStates {
+    Top,
+    #[parent(Top)]
+    On,
+    #[parent(On)]
+    #[initial]
+    Dimmer,
+    #[parent(Top)]
+    Off,
+}
+
+struct NoopState;
+
+impl StateIF<LightStates, Knock> for NoopState {
+    fn handle_event(&self, _event: &Knock) -> bool {
+        false
+    }
+}
+
+fn assert_state<StateT: rust_hsm::state::StateConstraint>(_: StateT) {}
+
+#[test]
+fn derived_states() {
+    assert_state(DoorStates::Top);
+    assert_state(DoorStates::Open);
+    assert_state(DoorStates::Closed);
+}
+
+#[test]
+fn derived_event_names() {
+    // Variant names, not their Display
+    assert_eq!(DoorEvents::Open.get_event_name(), "Open");
+    assert_eq!(DoorEvents::Lock(1234).get_event_name(), "Lock");
+    assert_eq!(
+        DoorEvents::Paint {
+            color: "red".to_string()
+        }
+        .get_event_name(),
+        "Paint"
+    );
+    assert_eq!(Knock.get_event_name(), "Knock knock");
+}
+
+#[test]
+fn declared_hierarchy() {
+    assert_eq!(
+        LightStates::PARENTS,
+        &[
+            (LightStates::Top, None),
+            (LightStates::On, Some(LightStates::Top)),
+            (LightStates::Dimmer, Some(LightStates::On)),
+            (LightStates::Off, Some(LightStates::Top)),
+        ]
+    );
+    assert_eq!(LightStates::Dimmer.declared_depth(), 2);
+    assert_eq!(LightStates::Off.declared_parent(), Some(LightStates::Top));
+    assert_eq!(
+        LightStates::On.declared_initial_child(),
+        Some(LightStates::Dimmer)
+    );
+    assert_eq!(LightStates::Top.declared_initial_child(), None);
+
+    let mut builder = HsmBuilder::new("Light".to_string(), log::LevelFilter::Info);
+    for state in [
+        LightStates::Top,
+        LightStates::On,
+        LightStates::Dimmer,
+        LightStates::Off,
+    ] {
+        builder.add_declared_state(Box::new(NoopState), state);
+    }
+    let hsm = builder.build(LightStates::On).unwrap();
+    assert_eq!(hsm.get_current_state().unwrap(), LightStates::Dimmer);
+    assert_eq!(hsm.depth_of(LightStates::Dimmer).unwrap(), 2);
+
+    // On added by hand under Off: Dimmer is no longer where it was declared
+    let mut builder = HsmBuilder::new("Light".to_string(), log::LevelFilter::Info);
+    builder
+        .add_declared_state(Box::new(NoopState), LightStates::Top)
+        .add_declared_state(Box::new(NoopState), LightStates::Off)
+        .add_state(Box::new(NoopState), LightStates::On, Some(LightStates::Off))
+        .add_declared_state(Box::new(NoopState), LightStates::Dimmer);
+    assert!(matches!(
+        builder.build(LightStates::Off),
+        Err(HSMError::InvalidTopology(issues))
+            if issues == vec![TopologyIssue::DepthMismatch {
+                state: LightStates::Dimmer,
+                declared: 2,
+                actual: 3,
+            }]
+    ));
+}
+
+#[test]
+fn compile_errors() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,22 @@
+use rust_hsm::hsm_states;
+
+#[hsm_states]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, strum::Display)]
+enum States {
+    Top,
+    Other,
+    #[parent(Top)]
+    #[initial]
+    A,
+    #[parent(Top)]
+    #[initial]
+    B,
+    #[parent(Missing)]
+    C,
+    #[parent(E)]
+    D,
+    #[parent(D)]
+    E,
+}
+
+fn main() {}
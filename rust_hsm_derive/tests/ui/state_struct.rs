@@ -0,0 +1,6 @@
+use rust_hsm::HsmState;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, HsmState)]
+struct States;
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rust_hsm::HsmState;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, strum::Display, HsmState)]
+enum States {
+    Top,
+    Level(u8),
+}
+
+fn main() {}